@@ -1,3 +1,19 @@
+use crate::auto_dj::{self, AutoDj};
+use crate::bulk_edit;
+use crate::constants::{DEFAULT_ORGANIZE_TEMPLATE, DEFAULT_PATH_TEMPLATE};
+use crate::cover_art::{CoverCache, GraphicsProtocol};
+use crate::duplicates;
+use crate::folder_tree::{self, FolderTree};
+use crate::history::{self, History, Play, PlayStats};
+use crate::lyrics::{self, Lyrics};
+use crate::organizer;
+use crate::playlists::{self, Playlist};
+use crate::prompt::TextInput;
+use crate::ratings;
+use crate::smart_playlists::SmartPlaylist;
+use crate::query::{self, Query};
+use crate::stats::LibraryStats;
+use crate::tag_editor::{self, TagEditor, TagField};
 use crate::load_album_and_track_lists;
 use crate::player;
 use crate::player::Player;
@@ -9,9 +25,10 @@ use ratatui::{
 	widgets::{ListState, TableState},
 };
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use std::usize;
+use std::path::{Path, PathBuf};
 
 pub struct App {
 	pub active_panel: ActivePanel,
@@ -20,16 +37,49 @@ pub struct App {
 	pub all_albums: Vec<load_album_and_track_lists::Album>,
 	pub all_tracks: Vec<load_album_and_track_lists::Track>,
 	pub queue: Vec<load_album_and_track_lists::Track>,
+	pub scan_issues: Vec<load_album_and_track_lists::ScanIssue>,
+	pub duplicates: Vec<duplicates::DuplicateGroup>,
+	verifying: Vec<duplicates::Verification>, // duplicate groups being hashed
+	pub stats: LibraryStats,
+	pub details: Option<load_album_and_track_lists::Track>, // track shown in the details popup
+	pub tag_editor: Option<TagEditor>,
+	pub bulk_changes: Vec<bulk_edit::BulkChange>,
+	pub organize_plan: Vec<organizer::PlannedMove>,
+	pub prompt: TextInput,
+	pub covers: CoverCache,
+	pub lyrics: Option<Lyrics>,
+	pub lyrics_path: Option<String>, // the track `lyrics` were loaded for
+	pub lyrics_scroll: usize,        // manual scroll offset for unsynced lyrics
 
 	pub config: AppConfig,
 	pub config_path: PathBuf,
 
+	pub artists: Vec<String>, // album artists, only filled in the cascading browser
+	pub artist_state: TableState,
+	pub folders: FolderTree,
+	pub folder_state: TableState, // index into `folders.visible()`
+	pub playlists: Vec<Playlist>,
+	pub playlist_state: TableState,
+	pub entry_state: TableState,                                  // rows of the selected playlist
+	pub playlist_pending: Vec<load_album_and_track_lists::Track>, // waiting for a playlist to be picked
+	pub smart_tracks: Vec<load_album_and_track_lists::Track>,     // of the selected smart playlist, found when it's opened
 	pub album_state: TableState,
 	pub track_state: TableState,
 	pub queue_state: ListState,
 	pub queue_index: Option<usize>,
+	stopped: bool, // stopped on purpose, so the queue isn't started over when the player runs dry
+	pub history: History,
+	play_started: Option<u64>, // when the playing track started, until it is recorded in `history`
+
+	pub popup: Option<Popup>,
+	pub popup_state: TableState,
 
 	pub sort_state: SortState,
+	pub sort_key: SortKey,
+	pub filter_rows: Vec<FilterRow>, // rows of the quick filters popup
+	pub marks: Marks,
+	undo: Vec<UndoStep>,
+	redo: Vec<UndoStep>,
 
 	pub player: player::Player,
 
@@ -39,6 +89,7 @@ pub struct App {
 	pub find_char_index: usize,
 
 	pub highlight_color: Color,
+	pub status: Option<String>, // short message shown in the player bar
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -47,13 +98,182 @@ pub enum SortState {
 	ZA,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What the Albums and Tracks panes are sorted by; SortState gives the direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+	Name, // albums by artist and name, tracks by title
+	Year,
+	Rating,     // most stars first
+	Plays,      // most first; albums by the plays of all their tracks
+	Skips,      // most first
+	LastPlayed, // most recent first
+}
+
+impl SortKey {
+	pub fn label(&self) -> &'static str {
+		match self {
+			SortKey::Name => "name",
+			SortKey::Year => "year",
+			SortKey::Rating => "rating",
+			SortKey::Plays => "plays",
+			SortKey::Skips => "skips",
+			SortKey::LastPlayed => "last played",
+		}
+	}
+
+	fn next(self) -> Self {
+		match self {
+			SortKey::Name => SortKey::Year,
+			SortKey::Year => SortKey::Rating,
+			SortKey::Rating => SortKey::Plays,
+			SortKey::Plays => SortKey::Skips,
+			SortKey::Skips => SortKey::LastPlayed,
+			SortKey::LastPlayed => SortKey::Name,
+		}
+	}
+}
+
+/// Rows marked in the Albums, Tracks or Queue pane, so that actions apply to
+/// all of them instead of only the selected row.
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+	panel: Option<ActivePanel>,
+	rows: BTreeSet<usize>,
+	anchor: Option<usize>, // visual mode: the rows from here to the cursor are marked too
+}
+
+impl Marks {
+	fn new(panel: ActivePanel) -> Self {
+		Self {
+			panel: Some(panel),
+			..Self::default()
+		}
+	}
+
+	/// The marked rows of `panel` in order, with `cursor` ending the visual range.
+	pub fn rows(&self, panel: ActivePanel, cursor: Option<usize>) -> Vec<usize> {
+		if self.panel != Some(panel) {
+			return Vec::new();
+		}
+		let mut rows = self.rows.clone();
+		if let (Some(anchor), Some(cursor)) = (self.anchor, cursor) {
+			rows.extend(anchor.min(cursor)..=anchor.max(cursor));
+		}
+		rows.into_iter().collect()
+	}
+
+	pub fn is_visual(&self) -> bool {
+		self.anchor.is_some()
+	}
+
+	/// Drop the marks of `panel`, whose rows have changed.
+	fn forget(&mut self, panel: ActivePanel) {
+		if self.panel == Some(panel) {
+			*self = Self::default();
+		}
+	}
+
+	/// Follow rows that all moved by `offset`.
+	fn shift(&mut self, offset: isize) {
+		self.rows = self
+			.rows
+			.iter()
+			.filter_map(|i| i.checked_add_signed(offset))
+			.collect();
+		self.anchor = self.anchor.and_then(|i| i.checked_add_signed(offset));
+	}
+}
+
+const UNDO_LIMIT: usize = 100; // steps kept for undo
+const AUTO_DJ_RECENT: usize = 200; // last plays the auto-DJ tries not to repeat
+
+/// The queue and favorites as they were before a change, to go back to with
+/// undo (or forward to with redo).
+#[derive(Debug, Clone)]
+struct UndoStep {
+	what: String, // "clearing the queue", for the status message
+	queue: Vec<load_album_and_track_lists::Track>,
+	queue_index: Option<usize>,
+	favorite_albums: Vec<(String, String)>,
+	favorite_tracks: Vec<(String, String)>,
+}
+
+/// A row of the quick filters popup: a label and the `key:value` it puts
+/// in the find field, or `None` to clear the filters.
+#[derive(Debug, Clone)]
+pub struct FilterRow {
+	pub label: String,
+	pub filter: Option<(&'static str, String)>,
+}
+
+/// Flat shows every album and track side by side; Cascade narrows
+/// Artists -> Albums -> Tracks by the selection in the pane to the left;
+/// Folders shows the library folders on disk next to the tracks in them;
+/// Playlists shows the saved playlists next to the entries of the selected one.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BrowseMode {
+	#[default]
+	Flat,
+	Cascade,
+	Folders,
+	Playlists,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ActivePanel {
+	Artists,
+	Folders,
+	Playlists,
+	Entries, // of the selected playlist
 	Albums,
 	Tracks,
 	Queue,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Popup {
+	LibraryIssues,
+	Duplicates,
+	TagEditor,
+	BulkEditConfirm,
+	Prompt(PromptKind),
+	OrganizePreview,
+	Filters,
+	Stats,
+	Details,
+	AddToPlaylist,
+	DeletePlaylist,
+	ExportQueue,
+	History,
+}
+
+/// What the text typed into the prompt popup is for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+	OrganizeRoot,
+	NewPlaylist,
+	RenamePlaylist,
+	NewSmartPlaylist,
+	EditSmartPlaylist,
+	ExportQueue(playlists::Format, bool), // format, library-relative paths
+	ImportQueue,
+}
+
+impl PromptKind {
+	pub fn title(&self) -> &'static str {
+		match self {
+			PromptKind::OrganizeRoot => " Organize files into",
+			PromptKind::NewPlaylist => " New playlist",
+			PromptKind::RenamePlaylist => " Rename playlist",
+			PromptKind::NewSmartPlaylist => " New smart playlist (name = rules)",
+			PromptKind::EditSmartPlaylist => " Edit smart playlist (name = rules)",
+			PromptKind::ExportQueue(..) => " Save the queue to",
+			PromptKind::ImportQueue => " Add to the queue from playlist file",
+		}
+	}
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum InputMode {
 	Normal,
@@ -65,7 +285,46 @@ pub struct AppConfig {
 	highlight_color: u8,
 	favorite_albums: Vec<(String, String)>, // (artist, album_name)
 	favorite_tracks: Vec<(String, String)>, // (artist, track_name)
+	#[serde(default)]
+	album_ratings: Vec<(String, String, u8)>, // (artist, album_name, stars)
+	#[serde(default)]
+	track_ratings: Vec<(String, String, u8)>, // (artist, track_name, stars); overrides the tags
+	#[serde(default)]
+	write_rating_tags: bool, // also save track ratings in the files, for other players
+	#[serde(default = "default_path_template")]
+	path_template: String, // e.g. "{artist}/{album}/{track} - {title}"
+	#[serde(default = "default_organize_template")]
+	organize_template: String, // where the organizer moves files
+	#[serde(default = "default_show_cover")]
+	show_cover: bool,
+	#[serde(default)]
+	cover_protocol: GraphicsProtocol, // auto, kitty, sixel or halfblocks
+	#[serde(default)]
+	show_lyrics: bool,
+	#[serde(default)]
+	browse_mode: BrowseMode,
+	#[serde(default)]
+	show_play_stats: bool, // plays, skips and last played columns in the Tracks pane
+	#[serde(default)]
+	smart_playlists: Vec<SmartPlaylist>, // sorted by name
+	#[serde(default)]
+	auto_dj: AutoDj,
+	#[serde(default)]
+	auto_dj_exclude: Vec<String>, // find terms, e.g. "genre:christmas"
+}
+
+fn default_path_template() -> String {
+	DEFAULT_PATH_TEMPLATE.to_string()
+}
+
+fn default_organize_template() -> String {
+	DEFAULT_ORGANIZE_TEMPLATE.to_string()
 }
+
+fn default_show_cover() -> bool {
+	true
+}
+
 impl AppConfig {
 	// favorites
 	pub fn is_album_favorite(&self, artist: &str, album_name: &str) -> bool {
@@ -105,6 +364,156 @@ impl AppConfig {
 			.retain(|(a, n)| !(a == artist && n == track_name));
 	}
 
+	// ratings
+
+	pub fn album_rating(&self, artist: &str, album_name: &str) -> Option<u8> {
+		self.album_ratings
+			.iter()
+			.find(|(a, n, _)| a == artist && n == album_name)
+			.map(|(_, _, stars)| *stars)
+	}
+
+	pub fn track_rating(&self, artist: &str, track_name: &str) -> Option<u8> {
+		self.track_ratings
+			.iter()
+			.find(|(a, n, _)| a == artist && n == track_name)
+			.map(|(_, _, stars)| *stars)
+	}
+
+	pub fn set_album_rating(&mut self, artist: &str, album_name: &str, stars: u8) {
+		self.album_ratings
+			.retain(|(a, n, _)| !(a == artist && n == album_name));
+		self.album_ratings
+			.push((artist.to_string(), album_name.to_string(), stars));
+	}
+
+	pub fn set_track_rating(&mut self, artist: &str, track_name: &str, stars: u8) {
+		self.track_ratings
+			.retain(|(a, n, _)| !(a == artist && n == track_name));
+		self.track_ratings
+			.push((artist.to_string(), track_name.to_string(), stars));
+	}
+
+	// renames
+
+	/// Give a track that was renamed in its tags the favorite flag and rating
+	/// of its old artist and title.
+	pub fn copy_track_entries(&mut self, (artist, track_name): (&str, &str), (new_artist, new_name): (&str, &str)) {
+		if self.is_track_favorite(artist, track_name) {
+			self.add_track_favorite(new_artist.to_string(), new_name.to_string());
+		}
+		if let Some(stars) = self.track_rating(artist, track_name)
+			&& self.track_rating(new_artist, new_name).is_none()
+		{
+			self.set_track_rating(new_artist, new_name, stars);
+		}
+	}
+
+	pub fn copy_album_entries(&mut self, (artist, album_name): (&str, &str), (new_artist, new_name): (&str, &str)) {
+		if self.is_album_favorite(artist, album_name) {
+			self.add_album_favorite(new_artist.to_string(), new_name.to_string());
+		}
+		if let Some(stars) = self.album_rating(artist, album_name)
+			&& self.album_rating(new_artist, new_name).is_none()
+		{
+			self.set_album_rating(new_artist, new_name, stars);
+		}
+	}
+
+	pub fn remove_track_entries(&mut self, artist: &str, track_name: &str) {
+		self.remove_track_favorite(artist, track_name);
+		self.track_ratings
+			.retain(|(a, n, _)| !(a == artist && n == track_name));
+	}
+
+	pub fn remove_album_entries(&mut self, artist: &str, album_name: &str) {
+		self.remove_album_favorite(artist, album_name);
+		self.album_ratings
+			.retain(|(a, n, _)| !(a == artist && n == album_name));
+	}
+
+	/// Albums used to be grouped, and so saved, by track artist. Move
+	/// favorites and ratings still under a track artist of an album to its
+	/// album artist, unless another album goes by the old name. Returns
+	/// whether anything moved.
+	pub fn migrate_album_keys(&mut self, albums: &[load_album_and_track_lists::Album]) -> bool {
+		let mut moved = false;
+		for album in albums {
+			let mut artists: Vec<&str> = album.tracks.iter().map(|t| t.artist.as_str()).collect();
+			artists.sort_unstable();
+			artists.dedup();
+			for artist in artists {
+				if albums.iter().any(|a| a.artist == artist && a.name == album.name) {
+					continue;
+				}
+				if self.is_album_favorite(artist, &album.name) || self.album_rating(artist, &album.name).is_some() {
+					self.copy_album_entries((artist, &album.name), (&album.artist, &album.name));
+					self.remove_album_entries(artist, &album.name);
+					moved = true;
+				}
+			}
+		}
+		moved
+	}
+
+	// library
+
+	pub fn path_template(&self) -> &str {
+		&self.path_template
+	}
+
+	pub fn organize_template(&self) -> &str {
+		&self.organize_template
+	}
+
+	// cover art
+	pub fn show_cover(&self) -> bool {
+		self.show_cover
+	}
+
+	pub fn toggle_cover(&mut self) {
+		self.show_cover = !self.show_cover;
+	}
+
+	pub fn cover_protocol(&self) -> GraphicsProtocol {
+		self.cover_protocol
+	}
+
+	// lyrics
+	pub fn show_lyrics(&self) -> bool {
+		self.show_lyrics
+	}
+
+	pub fn toggle_lyrics(&mut self) {
+		self.show_lyrics = !self.show_lyrics;
+	}
+
+	// browsing
+	pub fn browse_mode(&self) -> BrowseMode {
+		self.browse_mode
+	}
+
+	pub fn set_browse_mode(&mut self, mode: BrowseMode) {
+		self.browse_mode = mode;
+	}
+
+	pub fn show_play_stats(&self) -> bool {
+		self.show_play_stats
+	}
+
+	pub fn toggle_play_stats(&mut self) {
+		self.show_play_stats = !self.show_play_stats;
+	}
+
+	pub fn smart_playlists(&self) -> &[SmartPlaylist] {
+		&self.smart_playlists
+	}
+
+	// auto-DJ
+	pub fn auto_dj(&self) -> AutoDj {
+		self.auto_dj
+	}
+
 	// colors
 
 	pub fn get_color(&self) -> Color {
@@ -162,6 +571,19 @@ impl Default for AppConfig {
 			highlight_color: 0,          // red
 			favorite_albums: Vec::new(), // (artist, album_name)
 			favorite_tracks: Vec::new(), // (artist, track_name)
+			album_ratings: Vec::new(),
+			track_ratings: Vec::new(),
+			write_rating_tags: false,
+			path_template: default_path_template(),
+			organize_template: default_organize_template(),
+			show_cover: default_show_cover(),
+			cover_protocol: GraphicsProtocol::Auto,
+			show_lyrics: false,
+			browse_mode: BrowseMode::Flat,
+			show_play_stats: false,
+			smart_playlists: Vec::new(),
+			auto_dj: AutoDj::Off,
+			auto_dj_exclude: Vec::new(),
 		}
 	}
 }
@@ -170,39 +592,96 @@ impl App {
 	pub fn new(
 		albums: Vec<load_album_and_track_lists::Album>,
 		tracks: Vec<load_album_and_track_lists::Track>,
+		scan_issues: Vec<load_album_and_track_lists::ScanIssue>,
 		highlight_color: Color,
 		config: &AppConfig,
-		config_path: &PathBuf,
+		config_path: &Path,
 	) -> Self {
+		let mut artist_state = TableState::default();
+		artist_state.select(Some(0));
+
 		let mut album_state = TableState::default();
 		album_state.select(Some(0));
 
 		let mut track_state = TableState::default();
 		track_state.select(Some(0));
 
+		let mut folder_state = TableState::default();
+		folder_state.select(Some(0));
+
+		let mut playlist_state = TableState::default();
+		playlist_state.select(Some(0));
+
+		let mut entry_state = TableState::default();
+		entry_state.select(Some(0));
+
 		let mut queue_state = ListState::default();
 		queue_state.select(Some(0));
 
+		let folders = FolderTree::build(
+			Path::new(&load_album_and_track_lists::library_dir()),
+			&tracks,
+			&scan_issues,
+			HashSet::new(),
+		);
+
 		Self {
-			active_panel: ActivePanel::Albums,
+			active_panel: match config.browse_mode() {
+				BrowseMode::Folders => ActivePanel::Folders,
+				BrowseMode::Playlists => ActivePanel::Playlists,
+				_ => ActivePanel::Albums,
+			},
 			all_albums: albums.clone(),
 			all_tracks: tracks.clone(),
 			albums,
 			config: config.clone(),
-			config_path: config_path.clone(),
+			config_path: config_path.to_path_buf(),
 			tracks,
 			queue: Vec::new(),
+			scan_issues,
+			duplicates: Vec::new(),
+			verifying: Vec::new(),
+			stats: LibraryStats::default(),
+			details: None,
+			tag_editor: None,
+			bulk_changes: Vec::new(),
+			organize_plan: Vec::new(),
+			prompt: TextInput::default(),
+			covers: CoverCache::new(config.cover_protocol()),
+			lyrics: None,
+			lyrics_path: None,
+			lyrics_scroll: 0,
+			artists: Vec::new(),
+			artist_state,
+			folders,
+			folder_state,
+			playlists: playlists::load_all(),
+			playlist_state,
+			entry_state,
+			playlist_pending: Vec::new(),
+			smart_tracks: Vec::new(),
 			album_state,
 			track_state,
 			queue_state,
 			sort_state: SortState::AZ,
-			queue_index: Some(0),
+			sort_key: SortKey::Name,
+			filter_rows: Vec::new(),
+			marks: Marks::default(),
+			undo: Vec::new(),
+			redo: Vec::new(),
+			queue_index: None,
+			stopped: false,
+			history: History::load(&config_path.with_file_name("history.jsonl")),
+			play_started: None,
+			popup: None,
+			popup_state: TableState::default(),
 			player: Player::new(),
 			input: String::new(),
 			find_term: String::new(),
 			input_mode: InputMode::Normal,
 			find_char_index: 0,
 			highlight_color,
+			status: None,
 		}
 	}
 
@@ -226,15 +705,29 @@ impl App {
 
 	// navigation
 	pub fn move_left(&mut self) {
+		let mode = self.config.browse_mode();
+		self.clear_marks();
 		self.active_panel = match self.active_panel {
+			ActivePanel::Artists => ActivePanel::Artists,
+			ActivePanel::Folders => ActivePanel::Folders,
+			ActivePanel::Playlists => ActivePanel::Playlists,
+			ActivePanel::Entries => ActivePanel::Playlists,
+			ActivePanel::Albums if mode == BrowseMode::Cascade => ActivePanel::Artists,
 			ActivePanel::Albums => ActivePanel::Albums,
+			ActivePanel::Tracks if mode == BrowseMode::Folders => ActivePanel::Folders,
 			ActivePanel::Tracks => ActivePanel::Albums,
+			ActivePanel::Queue if mode == BrowseMode::Playlists => ActivePanel::Entries,
 			ActivePanel::Queue => ActivePanel::Tracks,
 		}
 	}
 
 	pub fn move_right(&mut self) {
+		self.clear_marks();
 		self.active_panel = match self.active_panel {
+			ActivePanel::Artists => ActivePanel::Albums,
+			ActivePanel::Folders => ActivePanel::Tracks,
+			ActivePanel::Playlists => ActivePanel::Entries,
+			ActivePanel::Entries => ActivePanel::Queue,
 			ActivePanel::Albums => ActivePanel::Tracks,
 			ActivePanel::Tracks => ActivePanel::Queue,
 			ActivePanel::Queue => ActivePanel::Queue,
@@ -243,17 +736,58 @@ impl App {
 
 	pub fn move_down(&mut self) {
 		match self.active_panel {
+			ActivePanel::Artists => {
+				let i = match self.artist_state.selected() {
+					Some(i) if i < self.artists.len().saturating_sub(1) => i + 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.artist_state.select(Some(i));
+				self.album_state.select(Some(0));
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			ActivePanel::Folders => {
+				let i = match self.folder_state.selected() {
+					Some(i) if i < self.folders.visible().len().saturating_sub(1) => i + 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.folder_state.select(Some(i));
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			ActivePanel::Playlists => {
+				let i = match self.playlist_state.selected() {
+					Some(i) if i < self.playlist_count().saturating_sub(1) => i + 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.select_playlist(Some(i));
+			}
+			ActivePanel::Entries => {
+				let i = match self.entry_state.selected() {
+					Some(i) if i < self.playlist_len().saturating_sub(1) => i + 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.entry_state.select(Some(i));
+			}
 			ActivePanel::Albums => {
 				let i = match self.album_state.selected() {
-					Some(i) if i < self.albums.len() - 1 => i + 1,
+					Some(i) if i < self.albums.len().saturating_sub(1) => i + 1,
 					Some(i) => i,
 					None => 0,
 				};
 				self.album_state.select(Some(i));
+				if self.config.browse_mode() == BrowseMode::Cascade {
+					self.track_state.select(Some(0));
+					self.cascade();
+				}
 			}
 			ActivePanel::Tracks => {
 				let i = match self.track_state.selected() {
-					Some(i) if i < self.tracks.len() - 1 => i + 1,
+					Some(i) if i < self.tracks.len().saturating_sub(1) => i + 1,
 					Some(i) => i,
 					None => 0,
 				};
@@ -272,6 +806,43 @@ impl App {
 
 	pub fn move_up(&mut self) {
 		match self.active_panel {
+			ActivePanel::Artists => {
+				let i = match self.artist_state.selected() {
+					Some(i) if i > 0 => i - 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.artist_state.select(Some(i));
+				self.album_state.select(Some(0));
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			ActivePanel::Folders => {
+				let i = match self.folder_state.selected() {
+					Some(i) if i > 0 => i - 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.folder_state.select(Some(i));
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			ActivePanel::Playlists => {
+				let i = match self.playlist_state.selected() {
+					Some(i) if i > 0 => i - 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.select_playlist(Some(i));
+			}
+			ActivePanel::Entries => {
+				let i = match self.entry_state.selected() {
+					Some(i) if i > 0 => i - 1,
+					Some(i) => i,
+					None => 0,
+				};
+				self.entry_state.select(Some(i));
+			}
 			ActivePanel::Albums => {
 				let i = match self.album_state.selected() {
 					Some(i) if i > 0 => i - 1,
@@ -279,6 +850,10 @@ impl App {
 					None => 0,
 				};
 				self.album_state.select(Some(i));
+				if self.config.browse_mode() == BrowseMode::Cascade {
+					self.track_state.select(Some(0));
+					self.cascade();
+				}
 			}
 			ActivePanel::Tracks => {
 				let i = match self.track_state.selected() {
@@ -300,157 +875,1603 @@ impl App {
 	}
 
 	pub fn main_action(&mut self) {
-		match self.active_panel {
-			ActivePanel::Albums => {
-				if let Some(i) = self.album_state.selected() {
-					let mut tracks = self.albums[i].tracks.clone();
-					self.queue.extend(tracks.drain(..));
-					self.queue_state
-						.select(Some(self.queue.len().saturating_sub(1)));
-				}
-				if self.player.current_track().is_none() {
-					self.start_play_at(0);
+		if let ActivePanel::Queue = self.active_panel {
+			let rows = self.target_rows();
+			self.clear_marks();
+			for &i in rows.iter().rev() {
+				if i < self.queue.len() {
+					self.remove_from_queue(i);
 				}
 			}
-			ActivePanel::Tracks => {
-				if let Some(i) = self.track_state.selected() {
-					self.queue.push(self.tracks[i].clone());
-					self.queue_state
-						.select(Some(self.queue.len().saturating_sub(1)));
-				}
-				if self.player.current_track().is_none() {
-					self.start_play_at(0);
-				}
+			if self.queue.is_empty() {
+				self.queue_state.select(None);
+			} else if let Some(&first) = rows.first() {
+				self.queue_state.select(Some(first.min(self.queue.len() - 1)));
 			}
-			ActivePanel::Queue => {
-				if let Some(i) = self.queue_state.selected() {
-					self.queue.remove(i);
-					if self.queue.is_empty() {
-						self.queue_state.select(None);
-					} else if i >= self.queue.len() {
-						self.queue_state.select(Some(self.queue.len() - 1));
-					}
-				}
+			return;
+		}
+		let tracks = self.selected_tracks();
+		self.clear_marks();
+		self.append_to_queue(tracks);
+	}
+
+	/// Play the selection next: insert it after the playing track, or move the
+	/// selected queue tracks there.
+	pub fn aux_main_action(&mut self) {
+		let tracks = if let ActivePanel::Queue = self.active_panel {
+			let rows: Vec<usize> = self
+				.target_rows()
+				.into_iter()
+				.filter(|i| *i < self.queue.len() && Some(*i) != self.queue_index)
+				.collect();
+			let tracks = rows.iter().map(|&i| self.queue[i].clone()).collect();
+			// none of them is playing, so the playing track only moves up
+			for &i in rows.iter().rev() {
+				self.remove_from_queue(i);
+			}
+			tracks
+		} else {
+			self.selected_tracks()
+		};
+		self.clear_marks();
+		if tracks.is_empty() {
+			return;
+		}
+		let next = self.queue_index.map_or(0, |c| c + 1);
+		self.insert_into_queue(next, tracks);
+		self.queue_state.select(Some(next));
+	}
+
+	// undo
+
+	fn undo_step(&self, what: String) -> UndoStep {
+		UndoStep {
+			what,
+			queue: self.queue.clone(),
+			queue_index: self.queue_index,
+			favorite_albums: self.config.favorite_albums.clone(),
+			favorite_tracks: self.config.favorite_tracks.clone(),
+		}
+	}
+
+	/// Run `action` so it can be undone, if it changes the queue or favorites.
+	pub fn undoable(&mut self, action: impl FnOnce(&mut Self)) {
+		let before = self.undo_step(String::new());
+		action(self);
+		let Some(what) = self.describe_change(&before) else {
+			return;
+		};
+		self.undo.push(UndoStep { what, ..before });
+		if self.undo.len() > UNDO_LIMIT {
+			self.undo.remove(0);
+		}
+		self.redo.clear();
+	}
+
+	/// What changed since `before`, as in "Undid clearing the queue".
+	fn describe_change(&self, before: &UndoStep) -> Option<String> {
+		if before.favorite_albums != self.config.favorite_albums
+			|| before.favorite_tracks != self.config.favorite_tracks
+		{
+			let cleared = self.config.favorite_albums.is_empty() && self.config.favorite_tracks.is_empty();
+			return Some(String::from(if cleared { "clearing all favorites" } else { "changing favorites" }));
+		}
+		let old: Vec<&str> = before.queue.iter().map(|t| t.path.as_str()).collect();
+		let new: Vec<&str> = self.queue.iter().map(|t| t.path.as_str()).collect();
+		if old == new {
+			return None;
+		}
+		// does every path of `short` appear in `long`, in the same order?
+		let within = |short: &[&str], long: &[&str]| {
+			let mut rest = long.iter();
+			short.iter().all(|p| rest.any(|q| q == p))
+		};
+		Some(if new.is_empty() {
+			String::from("clearing the queue")
+		} else if old.len() == new.len() {
+			String::from("reordering the queue")
+		} else if new.len() > old.len() && within(&old, &new) {
+			format!("adding {} to the queue", track_count(new.len() - old.len()))
+		} else if new.len() < old.len() && within(&new, &old) {
+			format!("removing {} from the queue", track_count(old.len() - new.len()))
+		} else {
+			String::from("replacing the queue")
+		})
+	}
+
+	pub fn undo(&mut self) {
+		match self.undo.pop() {
+			Some(step) => {
+				self.status = Some(format!("Undid {}", step.what));
+				let current = self.restore(step);
+				self.redo.push(current);
+			}
+			None => self.status = Some(String::from("Nothing to undo")),
+		}
+	}
+
+	pub fn redo(&mut self) {
+		match self.redo.pop() {
+			Some(step) => {
+				self.status = Some(format!("Redid {}", step.what));
+				let current = self.restore(step);
+				self.undo.push(current);
+			}
+			None => self.status = Some(String::from("Nothing to redo")),
+		}
+	}
+
+	/// Go back to `step`, returning the state it replaced.
+	fn restore(&mut self, step: UndoStep) -> UndoStep {
+		let current = self.undo_step(step.what.clone());
+
+		// files may have been edited or removed since; use them as they are now
+		let by_path: HashMap<&str, &load_album_and_track_lists::Track> =
+			self.all_tracks.iter().map(|t| (t.path.as_str(), t)).collect();
+		self.queue = step
+			.queue
+			.iter()
+			.filter_map(|t| by_path.get(t.path.as_str()).map(|t| (*t).clone()))
+			.collect();
+		// keep pointing at the track that is playing, which may have changed
+		let playing = self.player.current_track.as_ref().map(|t| t.path.clone());
+		self.queue_index = match playing {
+			Some(path) => step
+				.queue_index
+				.filter(|&i| self.queue.get(i).is_some_and(|t| t.path == path))
+				.or_else(|| self.queue.iter().position(|t| t.path == path)),
+			None => None,
+		};
+		self.queue_state.select(match self.queue.len() {
+			0 => None,
+			len => Some(self.queue_state.selected().unwrap_or(0).min(len - 1)),
+		});
+
+		if step.favorite_albums != self.config.favorite_albums
+			|| step.favorite_tracks != self.config.favorite_tracks
+		{
+			self.config.favorite_albums = step.favorite_albums;
+			self.config.favorite_tracks = step.favorite_tracks;
+			let config = &self.config;
+			for album in self.albums.iter_mut().chain(self.all_albums.iter_mut()) {
+				album.favorite = config.is_album_favorite(&album.artist, &album.name);
+			}
+			for track in self.tracks.iter_mut().chain(self.all_tracks.iter_mut()) {
+				track.favorite = config.is_track_favorite(&track.artist, &track.track_name);
+			}
+			self.sort_lists_with_favorites();
+			self.config.save(&self.config_path);
+		}
+		self.clear_marks();
+		current
+	}
+
+	// marks
+
+	/// The selected row of `panel`, if rows can be marked there.
+	fn cursor(&self, panel: ActivePanel) -> Option<usize> {
+		match panel {
+			ActivePanel::Albums => self.album_state.selected(),
+			ActivePanel::Tracks => self.track_state.selected(),
+			ActivePanel::Queue => self.queue_state.selected(),
+			_ => None,
+		}
+	}
+
+	/// The marked rows of `panel`, including a visual range being made.
+	pub fn marked_rows(&self, panel: ActivePanel) -> Vec<usize> {
+		self.marks.rows(panel, self.cursor(panel))
+	}
+
+	/// The rows an action in the active pane applies to: the marked rows,
+	/// or the selected one if nothing is marked.
+	fn target_rows(&self) -> Vec<usize> {
+		let rows = self.marked_rows(self.active_panel);
+		if rows.is_empty() {
+			self.cursor(self.active_panel).into_iter().collect()
+		} else {
+			rows
+		}
+	}
+
+	pub fn has_marks(&self) -> bool {
+		!self.marked_rows(self.active_panel).is_empty()
+	}
+
+	/// Mark or unmark the selected row and move to the next one.
+	pub fn toggle_mark(&mut self) {
+		let panel = self.active_panel;
+		let Some(i) = self.cursor(panel) else {
+			return;
+		};
+		if self.marks.panel != Some(panel) {
+			self.marks = Marks::new(panel);
+		}
+		if !self.marks.rows.remove(&i) {
+			self.marks.rows.insert(i);
+		}
+		self.move_down();
+	}
+
+	/// Start marking the rows the cursor moves over, or stop and keep them marked.
+	pub fn toggle_visual(&mut self) {
+		let panel = self.active_panel;
+		let Some(i) = self.cursor(panel) else {
+			return;
+		};
+		if self.marks.panel != Some(panel) {
+			self.marks = Marks::new(panel);
+		}
+		match self.marks.anchor.take() {
+			Some(anchor) => self.marks.rows.extend(anchor.min(i)..=anchor.max(i)),
+			None => self.marks.anchor = Some(i),
+		}
+	}
+
+	pub fn clear_marks(&mut self) {
+		self.marks = Marks::default();
+	}
+
+	// popups
+
+	/// Open `popup`, or close it if it is already open.
+	pub fn toggle_popup(&mut self, popup: Popup) {
+		if self.popup == Some(popup) {
+			self.popup = None;
+		} else {
+			if popup == Popup::Duplicates {
+				self.duplicates = duplicates::find_duplicates(&self.all_tracks);
+			}
+			if popup == Popup::Filters {
+				self.filter_rows = self.build_filter_rows();
 			}
+			if popup == Popup::Stats {
+				self.stats = LibraryStats::new(&self.all_tracks, &self.all_albums);
+			}
+			self.popup = Some(popup);
+			self.popup_state.select(Some(0));
+		}
+	}
+
+	pub fn close_popup(&mut self) {
+		self.popup = None;
+		self.details = None;
+		self.playlist_pending.clear();
+		self.tag_editor = None;
+		self.bulk_changes.clear();
+		self.organize_plan.clear();
+	}
+
+	pub fn open_prompt(&mut self, kind: PromptKind, value: &str) {
+		self.prompt = TextInput::new(value);
+		self.popup = Some(Popup::Prompt(kind));
+	}
+
+	pub fn submit_prompt(&mut self, kind: PromptKind) {
+		let value = self.prompt.value.trim().to_string();
+		let pending = std::mem::take(&mut self.playlist_pending);
+		self.close_popup();
+		match kind {
+			PromptKind::OrganizeRoot => self.plan_organize(&value),
+			PromptKind::NewPlaylist => self.create_playlist(&value, &pending),
+			PromptKind::RenamePlaylist => self.rename_playlist(&value),
+			PromptKind::NewSmartPlaylist => self.save_smart_playlist(&value, None),
+			PromptKind::EditSmartPlaylist => {
+				let i = self.playlist_state.selected().and_then(|i| i.checked_sub(self.playlists.len()));
+				self.save_smart_playlist(&value, i)
+			}
+			PromptKind::ExportQueue(format, relative) => self.export_queue(&value, format, relative),
+			PromptKind::ImportQueue => self.import_queue(&value),
+		}
+	}
+
+	fn popup_len(&self) -> usize {
+		match self.popup {
+			Some(Popup::LibraryIssues) => self.scan_issues.len(),
+			Some(Popup::Duplicates) => self.duplicate_rows().len(),
+			Some(Popup::BulkEditConfirm) => self.bulk_changes.len(),
+			Some(Popup::OrganizePreview) => self.organize_plan.len(),
+			Some(Popup::Filters) => self.filter_rows.len(),
+			Some(Popup::AddToPlaylist) => self.playlists.len() + 1,
+			Some(Popup::ExportQueue) => export_choices().len(),
+			Some(Popup::History) => self.history.plays.len(),
+			Some(Popup::TagEditor) | Some(Popup::Prompt(_)) | Some(Popup::Stats) | Some(Popup::Details) => 0,
+			Some(Popup::DeletePlaylist) | None => 0,
 		}
 	}
 
-	pub fn aux_main_action(&mut self) {
-		match self.active_panel {
-			ActivePanel::Albums => {
-				if let Some(i) = self.album_state.selected() {
-					let mut tracks = self.albums[i].tracks.clone();
-					while let Some(t) = tracks.pop() {
-						self.queue.insert(0, t);
-					}
-					self.queue_state.select(Some(0));
-				}
-			}
-			ActivePanel::Tracks => {
-				if let Some(i) = self.track_state.selected() {
-					let t = self.tracks[i].clone();
-					self.queue.insert(0, t);
-					self.queue_state.select(Some(0));
-				}
-			}
-			ActivePanel::Queue => {
-				if let Some(i) = self.queue_state.selected() {
-					if i < self.queue.len() {
-						let t = self.queue.remove(i);
-						self.queue.insert(0, t);
-						self.queue_state.select(Some(0));
-					}
-				}
+	/// Rows of the duplicates popup as (group, copy); `None` is the group header.
+	pub fn duplicate_rows(&self) -> Vec<(usize, Option<usize>)> {
+		let mut rows = Vec::new();
+		for (g, group) in self.duplicates.iter().enumerate() {
+			rows.push((g, None));
+			rows.extend((0..group.copies.len()).map(|c| (g, Some(c))));
+		}
+		rows
+	}
+
+	/// Start confirming the selected duplicate group by hashing the decoded audio.
+	pub fn verify_selected_duplicate(&mut self) {
+		if let Some(i) = self.popup_state.selected()
+			&& let Some(&(g, _)) = self.duplicate_rows().get(i)
+			&& !self.is_verifying(&self.duplicates[g])
+		{
+			let paths = self.duplicates[g].paths();
+			self.verifying.push(duplicates::Verification::start(paths));
+		}
+	}
+
+	pub fn is_verifying(&self, group: &duplicates::DuplicateGroup) -> bool {
+		let paths = group.paths();
+		self.verifying.iter().any(|v| v.paths == paths)
+	}
+
+	/// Take the hashes of the duplicate checks that have finished.
+	pub fn poll_verifications(&mut self) {
+		let mut done = Vec::new();
+		self.verifying.retain(|v| match v.finished() {
+			Some(hashes) => {
+				done.push((v.paths.clone(), hashes));
+				false
+			}
+			None => true,
+		});
+		for (paths, hashes) in done {
+			if let Some(group) = self.duplicates.iter_mut().find(|g| g.paths() == paths) {
+				group.hashes = Some(hashes);
+			}
+		}
+	}
+
+	pub fn popup_move_down(&mut self) {
+		let len = self.popup_len();
+		let i = match self.popup_state.selected() {
+			Some(i) if i < len.saturating_sub(1) => i + 1,
+			Some(i) => i,
+			None => 0,
+		};
+		self.popup_state.select(Some(i));
+	}
+
+	pub fn popup_move_up(&mut self) {
+		let i = match self.popup_state.selected() {
+			Some(i) if i > 0 => i - 1,
+			Some(i) => i,
+			None => 0,
+		};
+		self.popup_state.select(Some(i));
+	}
+
+	/// Show everything known about the selected track: the one selected in the
+	/// Tracks or Queue pane, otherwise the one playing or the first of the selection.
+	pub fn open_details(&mut self) {
+		let track = match self.active_panel {
+			ActivePanel::Tracks | ActivePanel::Queue => self.selected_tracks().into_iter().next(),
+			_ => self
+				.player
+				.current_track()
+				.or_else(|| self.selected_tracks().into_iter().next()),
+		};
+		if track.is_some() {
+			self.details = track;
+			self.popup = Some(Popup::Details);
+		}
+	}
+
+	// cover art
+
+	/// The track whose album cover is shown: the one playing, otherwise the
+	/// selected album, or the selected track when browsing folders.
+	pub fn cover_track(&self) -> Option<load_album_and_track_lists::Track> {
+		self.player.current_track().or_else(|| {
+			if self.config.browse_mode() == BrowseMode::Folders {
+				return self
+					.track_state
+					.selected()
+					.and_then(|i| self.tracks.get(i))
+					.cloned();
+			}
+			self.album_state
+				.selected()
+				.and_then(|i| self.albums.get(i))
+				.and_then(|a| a.tracks.first().cloned())
+		})
+	}
+
+	pub fn toggle_cover(&mut self) {
+		self.config.toggle_cover();
+		self.config.save(&self.config_path);
+	}
+
+	// lyrics
+
+	pub fn toggle_lyrics(&mut self) {
+		self.config.toggle_lyrics();
+		self.config.save(&self.config_path);
+	}
+
+	/// Load the lyrics of the playing track if it changed since the last call.
+	pub fn refresh_lyrics(&mut self) {
+		let path = self.player.current_track().map(|t| t.path);
+		if path != self.lyrics_path {
+			self.lyrics = path.as_deref().and_then(lyrics::load);
+			self.lyrics_path = path;
+			self.lyrics_scroll = 0;
+		}
+	}
+
+	pub fn scroll_lyrics_down(&mut self) {
+		if let Some(lyrics) = &self.lyrics
+			&& self.lyrics_scroll + 1 < lyrics.lines.len()
+		{
+			self.lyrics_scroll += 1;
+		}
+	}
+
+	pub fn scroll_lyrics_up(&mut self) {
+		self.lyrics_scroll = self.lyrics_scroll.saturating_sub(1);
+	}
+
+	// tag editing
+
+	/// The tracks the selected row stands for: a whole album, a track, or a queue entry.
+	fn selected_tracks(&self) -> Vec<load_album_and_track_lists::Track> {
+		match self.active_panel {
+			ActivePanel::Artists => self.artist_tracks(),
+			ActivePanel::Folders => self.folder_tracks(),
+			ActivePanel::Playlists => self.playlist_tracks(),
+			ActivePanel::Entries if self.selected_smart_playlist().is_some() => self
+				.entry_state
+				.selected()
+				.and_then(|i| self.smart_tracks.get(i).cloned())
+				.into_iter()
+				.collect(),
+			ActivePanel::Entries => self
+				.entry_state
+				.selected()
+				.and_then(|i| self.playlist_rows().get(i).and_then(|(_, track)| track.cloned()))
+				.into_iter()
+				.collect(),
+			ActivePanel::Albums => self
+				.target_rows()
+				.into_iter()
+				.filter_map(|i| self.albums.get(i))
+				.flat_map(|a| a.tracks.clone())
+				.collect(),
+			ActivePanel::Tracks => self
+				.target_rows()
+				.into_iter()
+				.filter_map(|i| self.tracks.get(i))
+				.cloned()
+				.collect(),
+			ActivePanel::Queue => self
+				.target_rows()
+				.into_iter()
+				.filter_map(|i| self.queue.get(i))
+				.cloned()
+				.collect(),
+		}
+	}
+
+	pub fn open_tag_editor(&mut self) {
+		let tracks = self.selected_tracks();
+		if tracks.is_empty() {
+			return;
+		}
+		self.tag_editor = Some(TagEditor::new(&tracks));
+		self.popup = Some(Popup::TagEditor);
+	}
+
+	/// Write the editor's changes to every edited file, keeping the popup
+	/// open with an error message if anything fails.
+	pub fn save_tag_editor(&mut self) {
+		let Some(editor) = &mut self.tag_editor else {
+			return;
+		};
+		let changes = editor.changes();
+		if let Some(err) = changes
+			.iter()
+			.find_map(|(field, value)| tag_editor::validate(*field, value).err())
+		{
+			editor.error = Some(err);
+			return;
+		}
+		let edits: Vec<(String, Vec<(TagField, String)>)> = editor
+			.paths
+			.iter()
+			.map(|p| (p.clone(), changes.clone()))
+			.collect();
+		match self.apply_tag_changes(&edits) {
+			Ok(()) => self.close_popup(),
+			Err(err) => {
+				if let Some(editor) = &mut self.tag_editor {
+					editor.error = Some(err);
+				}
+			}
+		}
+	}
+
+	/// Write tag changes to the files and update the in-memory library to
+	/// match, without rescanning. Stops at the first file that fails.
+	pub fn apply_tag_changes(
+		&mut self,
+		edits: &[(String, Vec<(TagField, String)>)],
+	) -> Result<(), String> {
+		let mut result = Ok(());
+		let before: Vec<load_album_and_track_lists::Track> = self
+			.all_tracks
+			.iter()
+			.filter(|t| edits.iter().any(|(path, changes)| !changes.is_empty() && *path == t.path))
+			.cloned()
+			.collect();
+		for (path, changes) in edits {
+			if changes.is_empty() {
+				continue;
+			}
+			if let Err(e) = tag_editor::write_tags(path, changes) {
+				result = Err(format!("{}: {}", path, e));
+				break;
+			}
+			let update = |track: &mut load_album_and_track_lists::Track| {
+				if &track.path == path {
+					for (field, value) in changes {
+						field.set(track, value);
+					}
+				}
+			};
+			self.all_tracks.iter_mut().for_each(update);
+			self.queue.iter_mut().for_each(update);
+			if let Some(track) = &mut self.player.current_track {
+				update(track);
+			}
+		}
+		self.follow_renames(&before);
+		self.refresh_library();
+		result
+	}
+
+	/// Favorites and ratings are kept by artist and name, so move them along
+	/// when tags are edited. The old entries stay while other tracks still
+	/// go by the old names.
+	fn follow_renames(&mut self, before: &[load_album_and_track_lists::Track]) {
+		let mut renamed = false;
+		for old in before {
+			let Some(new) = self.all_tracks.iter().find(|t| t.path == old.path) else {
+				continue;
+			};
+			if (&new.artist, &new.track_name) != (&old.artist, &old.track_name) {
+				self.config.copy_track_entries(
+					(&old.artist, &old.track_name),
+					(&new.artist, &new.track_name),
+				);
+				renamed = true;
+			}
+			if (&new.album_artist, &new.album) != (&old.album_artist, &old.album) {
+				self.config.copy_album_entries(
+					(&old.album_artist, &old.album),
+					(&new.album_artist, &new.album),
+				);
+				renamed = true;
+			}
+		}
+		if !renamed {
+			return;
+		}
+		for old in before {
+			if !self.all_tracks.iter().any(|t| t.artist == old.artist && t.track_name == old.track_name) {
+				self.config.remove_track_entries(&old.artist, &old.track_name);
+			}
+			if !self.all_tracks.iter().any(|t| t.album_artist == old.album_artist && t.album == old.album) {
+				self.config.remove_album_entries(&old.album_artist, &old.album);
+			}
+		}
+		self.config.save(&self.config_path);
+	}
+
+	/// Tracks for bulk actions: the selected album, the visible (searched)
+	/// tracks, or the whole queue, depending on the focused pane.
+	pub fn bulk_selection(&self) -> Vec<load_album_and_track_lists::Track> {
+		match self.active_panel {
+			ActivePanel::Artists
+			| ActivePanel::Folders
+			| ActivePanel::Playlists
+			| ActivePanel::Albums => self.selected_tracks(),
+			ActivePanel::Entries => self.playlist_tracks(),
+			ActivePanel::Tracks | ActivePanel::Queue if self.has_marks() => self.selected_tracks(),
+			ActivePanel::Tracks => self.tracks.clone(),
+			ActivePanel::Queue => self.queue.clone(),
+		}
+	}
+
+	/// Diff the edited dump against the original tracks and ask for
+	/// confirmation if anything changed.
+	pub fn review_bulk_edit(&mut self, contents: &str, originals: &[load_album_and_track_lists::Track]) {
+		match bulk_edit::parse(contents, originals) {
+			Ok(changes) if changes.is_empty() => {
+				self.status = Some(String::from("No tag changes"));
+			}
+			Ok(changes) => {
+				self.bulk_changes = changes;
+				self.popup = Some(Popup::BulkEditConfirm);
+				self.popup_state.select(Some(0));
+			}
+			Err(e) => self.status = Some(format!("Bulk edit failed: {}", e)),
+		}
+	}
+
+	pub fn apply_bulk_edit(&mut self) {
+		let edits = bulk_edit::group_by_path(&self.bulk_changes);
+		let fields = self.bulk_changes.len();
+		self.status = Some(match self.apply_tag_changes(&edits) {
+			Ok(()) => format!("Updated {} fields in {} files", fields, edits.len()),
+			Err(e) => format!("Bulk edit stopped: {}", e),
+		});
+		self.close_popup();
+	}
+
+	// organize
+
+	pub fn open_organize(&mut self) {
+		if self.bulk_selection().is_empty() {
+			return;
+		}
+		self.open_prompt(PromptKind::OrganizeRoot, &load_album_and_track_lists::library_dir());
+	}
+
+	/// Work out the moves for the current selection and show them for review.
+	fn plan_organize(&mut self, root: &str) {
+		if root.is_empty() {
+			return;
+		}
+		let tracks = self.bulk_selection();
+		self.organize_plan = organizer::plan(&tracks, Path::new(root), self.config.organize_template());
+		if self.organize_plan.is_empty() {
+			self.status = Some(String::from("All files are already in place"));
+			return;
+		}
+		self.popup = Some(Popup::OrganizePreview);
+		self.popup_state.select(Some(0));
+	}
+
+	pub fn run_organize(&mut self) {
+		let library_dir = load_album_and_track_lists::library_dir();
+		let (moved, errors) = organizer::execute(&self.organize_plan, Path::new(&library_dir));
+		let skipped = self.organize_plan.len() - moved.len();
+		self.rename_track_paths(&moved);
+		self.status = Some(match errors.first() {
+			Some(e) => format!("Moved {} files, {} skipped ({})", moved.len(), skipped, e),
+			None if skipped > 0 => format!("Moved {} files, {} skipped", moved.len(), skipped),
+			None => format!("Moved {} files", moved.len()),
+		});
+		self.close_popup();
+	}
+
+	/// Point everything that refers to a moved file at its new path.
+	pub fn rename_track_paths(&mut self, moved: &[(String, String)]) {
+		if moved.is_empty() {
+			return;
+		}
+		let renames: HashMap<&str, &str> = moved.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+		let update = |track: &mut load_album_and_track_lists::Track| {
+			if let Some(new) = renames.get(track.path.as_str()) {
+				track.path = new.to_string();
+			}
+		};
+		self.all_tracks.iter_mut().for_each(update);
+		self.queue.iter_mut().for_each(update);
+		for step in self.undo.iter_mut().chain(self.redo.iter_mut()) {
+			step.queue.iter_mut().for_each(update);
+		}
+		if let Some(track) = &mut self.player.current_track {
+			update(track);
+		}
+		for playlist in self.playlists.iter_mut() {
+			let mut changed = false;
+			for entry in playlist.entries.iter_mut() {
+				if let Some(new) = renames.get(entry.path.as_str()) {
+					entry.path = new.to_string();
+					changed = true;
+				}
+			}
+			if changed {
+				playlist.save().ok();
+			}
+		}
+		self.refresh_library();
+	}
+
+	/// Regroup albums from `all_tracks` and rebuild the visible lists,
+	/// keeping the current find term and selections.
+	pub fn refresh_library(&mut self) {
+		self.clear_marks();
+		// albums may have been renamed or moved, or got a new picture
+		self.covers.clear();
+		if let Ok(albums) = load_album_and_track_lists::create_album_list(self.all_tracks.clone()) {
+			self.all_albums = albums;
+		}
+		for album in self.all_albums.iter_mut() {
+			album.favorite = self.config.is_album_favorite(&album.artist, &album.name);
+			album.rating = self.config.album_rating(&album.artist, &album.name).unwrap_or_default();
+		}
+		let album_selected = self.album_state.selected();
+		let track_selected = self.track_state.selected();
+		self.find_albums();
+		self.find_tracks();
+		self.album_state
+			.select(album_selected.map(|i| i.min(self.albums.len().saturating_sub(1))));
+		self.track_state
+			.select(track_selected.map(|i| i.min(self.tracks.len().saturating_sub(1))));
+		self.folders = FolderTree::build(
+			Path::new(&load_album_and_track_lists::library_dir()),
+			&self.all_tracks,
+			&self.scan_issues,
+			self.folders.expanded(),
+		);
+		self.folder_state.select(
+			self.folder_state
+				.selected()
+				.map(|i| i.min(self.folders.visible().len().saturating_sub(1))),
+		);
+		self.cascade();
+	}
+
+	// queue
+
+	pub fn add_all_tracks_to_queue(&mut self) {
+		let first_new = self.queue.len();
+		for i in self.tracks.clone() {
+			self.queue.push(i)
+		}
+		self.queue_state
+			.select(Some(self.queue.len().saturating_sub(1)));
+		if self.player.current_track().is_none() {
+			self.start_play_at(first_new);
+		}
+	}
+
+	// queue editing; these keep `queue_index` on the playing track
+
+	fn insert_into_queue(&mut self, at: usize, tracks: Vec<load_album_and_track_lists::Track>) {
+		insert_rows(&mut self.queue, &mut self.queue_index, at, tracks);
+	}
+
+	/// Remove the track at `i`. Removing the playing track plays the one after
+	/// it, or stops if it was the last one.
+	fn remove_from_queue(&mut self, i: usize) {
+		if remove_row(&mut self.queue, &mut self.queue_index, i) {
+			if i < self.queue.len() {
+				self.start_play_at(i);
+			} else {
+				self.stop();
+			}
+		}
+	}
+
+	/// Move the selected (or marked) queue tracks to the end of the queue.
+	pub fn move_to_end(&mut self) {
+		let ActivePanel::Queue = self.active_panel else {
+			return;
+		};
+		let rows = self.target_rows();
+		self.clear_marks();
+		move_rows_to_end(&mut self.queue, &mut self.queue_index, &rows);
+		if !rows.is_empty() {
+			self.queue_state.select(self.queue.len().checked_sub(1));
+		}
+	}
+
+	/// Empty the queue. The history is kept.
+	pub fn clear_queue(&mut self) {
+		self.queue.clear();
+		self.queue_state.select(None);
+		self.queue_index = None;
+	}
+	/// Shuffle the queue; the playing track goes first so the rest plays after it.
+	pub fn shuffle_queue(&mut self) {
+		self.marks.forget(ActivePanel::Queue);
+		let mut rng = rng();
+		let playing = self
+			.queue_index
+			.filter(|c| *c < self.queue.len())
+			.map(|c| self.queue.remove(c));
+		self.queue.shuffle(&mut rng);
+		if let Some(track) = playing {
+			self.queue.insert(0, track);
+			self.queue_index = Some(0);
+		}
+
+		if !self.queue.is_empty() {
+			self.queue_state.select(Some(0));
+		} else {
+			self.queue_state.select(None);
+			self.queue_index = None;
+		}
+	}
+
+	// sort
+
+	pub fn toggle_sort(&mut self) {
+		self.sort_state = match self.sort_state {
+			SortState::AZ => SortState::ZA,
+			SortState::ZA => SortState::AZ,
+		};
+		self.sort_lists_with_favorites();
+	}
+
+	pub fn cycle_sort_key(&mut self) {
+		self.sort_key = self.sort_key.next();
+		self.sort_lists_with_favorites();
+		self.status = Some(format!("Sorted by {}", self.sort_key.label()));
+	}
+
+	pub fn sort_lists(&mut self) {
+		self.sort_lists_with_favorites();
+	}
+
+	fn sort_lists_with_favorites(&mut self) {
+		self.clear_marks();
+		let sort_albums = album_order(self.sort_state, self.sort_key);
+		let sort_tracks = track_order(self.sort_state, self.sort_key);
+
+		self.albums.sort_by(sort_albums);
+		self.tracks.sort_by(sort_tracks);
+		self.cascade();
+	}
+
+	// browsing
+
+	pub fn toggle_browse_mode(&mut self) {
+		self.clear_marks();
+		let mode = match self.config.browse_mode() {
+			BrowseMode::Flat => BrowseMode::Cascade,
+			BrowseMode::Cascade => BrowseMode::Folders,
+			BrowseMode::Folders => BrowseMode::Playlists,
+			BrowseMode::Playlists => BrowseMode::Flat,
+		};
+		self.config.set_browse_mode(mode);
+		self.config.save(&self.config_path);
+		match mode {
+			BrowseMode::Cascade => {
+				if let ActivePanel::Folders = self.active_panel {
+					self.active_panel = ActivePanel::Artists;
+				}
+				self.artist_state.select(Some(0));
+				self.album_state.select(Some(0));
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			BrowseMode::Folders => {
+				if let ActivePanel::Artists | ActivePanel::Albums = self.active_panel {
+					self.active_panel = ActivePanel::Folders;
+				}
+				self.track_state.select(Some(0));
+				self.cascade();
+			}
+			BrowseMode::Playlists => {
+				if !matches!(self.active_panel, ActivePanel::Queue) {
+					self.active_panel = ActivePanel::Playlists;
+				}
+				self.playlists = playlists::load_all();
+				self.select_playlist(Some(0));
+			}
+			BrowseMode::Flat => {
+				if let ActivePanel::Playlists | ActivePanel::Entries = self.active_panel {
+					self.active_panel = ActivePanel::Albums;
+				}
+				self.find_albums();
+				self.find_tracks();
+			}
+		}
+	}
+
+	/// In the cascading browser, narrow the Artists, Albums and Tracks panes
+	/// to the selection in the pane to their left. Selections are kept by
+	/// name where possible, since the lists get rebuilt. In the folder
+	/// browser, the Tracks pane shows the selected folder instead.
+	pub fn cascade(&mut self) {
+		self.marks.forget(ActivePanel::Tracks);
+		match self.config.browse_mode() {
+			BrowseMode::Flat | BrowseMode::Playlists => return,
+			BrowseMode::Folders => {
+				self.tracks = self.folder_tracks();
+				self.track_state.select(
+					self.track_state
+						.selected()
+						.map(|i| i.min(self.tracks.len().saturating_sub(1))),
+				);
+				return;
+			}
+			BrowseMode::Cascade => {}
+		}
+		let query = Query::parse(&self.find_term);
+		let matches = |album: &&load_album_and_track_lists::Album| {
+			query.matches_album(album) || album.tracks.iter().any(|t| query.matches_track(t))
+		};
+		let visible: Vec<&load_album_and_track_lists::Album> =
+			self.all_albums.iter().filter(matches).collect();
+
+		// artists
+		let selected_artist = self
+			.artist_state
+			.selected()
+			.and_then(|i| self.artists.get(i))
+			.cloned();
+		let mut artists: Vec<String> = visible.iter().map(|a| a.artist.clone()).collect();
+		artists.sort_by_key(|a| a.to_lowercase());
+		artists.dedup();
+		if let SortState::ZA = self.sort_state {
+			artists.reverse();
+		}
+		let artist_index = selected_artist
+			.and_then(|name| artists.iter().position(|a| *a == name))
+			.or(self.artist_state.selected())
+			.map(|i| i.min(artists.len().saturating_sub(1)));
+		self.artist_state.select(artist_index);
+		let artist = artist_index.and_then(|i| artists.get(i)).cloned();
+		self.artists = artists;
+
+		// albums of that artist
+		let selected_album = self
+			.album_state
+			.selected()
+			.and_then(|i| self.albums.get(i))
+			.map(|a| (a.artist.clone(), a.name.clone()));
+		let mut albums: Vec<load_album_and_track_lists::Album> = visible
+			.into_iter()
+			.filter(|a| Some(&a.artist) == artist.as_ref())
+			.cloned()
+			.collect();
+		albums.sort_by(album_order(self.sort_state, self.sort_key));
+		let album_index = selected_album
+			.and_then(|(artist, name)| {
+				albums.iter().position(|a| a.artist == artist && a.name == name)
+			})
+			.or(self.album_state.selected())
+			.map(|i| i.min(albums.len().saturating_sub(1)));
+		self.album_state.select(album_index);
+		self.albums = albums;
+
+		// tracks of that album, in disc/track order
+		let mut tracks: Vec<load_album_and_track_lists::Track> =
+			match album_index.and_then(|i| self.albums.get(i)) {
+				Some(album) => self
+					.all_tracks
+					.iter()
+					.filter(|t| t.album_artist == album.artist && t.album == album.name)
+					.cloned()
+					.collect(),
+				None => Vec::new(),
+			};
+		load_album_and_track_lists::sort_album_tracks(&mut tracks);
+		self.track_state.select(
+			self.track_state
+				.selected()
+				.map(|i| i.min(tracks.len().saturating_sub(1))),
+		);
+		self.tracks = tracks;
+	}
+
+	/// Every track of the albums shown for the selected artist.
+	fn artist_tracks(&self) -> Vec<load_album_and_track_lists::Track> {
+		self.albums.iter().flat_map(|a| a.tracks.clone()).collect()
+	}
+
+	/// Every track in the selected folder and below it, in filename order.
+	fn folder_tracks(&self) -> Vec<load_album_and_track_lists::Track> {
+		match self.selected_folder() {
+			Some(path) => folder_tree::tracks_in(&path, &self.all_tracks),
+			None => Vec::new(),
+		}
+	}
+
+	fn selected_folder(&self) -> Option<PathBuf> {
+		let i = self.folder_state.selected()?;
+		self.folders.visible().get(i).map(|f| f.path.clone())
+	}
+
+	/// Open or close the selected folder, or play the selected playlist.
+	pub fn open_selected(&mut self) {
+		match self.active_panel {
+			ActivePanel::Folders => {
+				if let Some(path) = self.selected_folder() {
+					self.folders.toggle(&path);
+				}
+			}
+			ActivePanel::Playlists => self.play_playlist(),
+			_ => {}
+		}
+	}
+
+	// playlists
+
+	fn selected_playlist(&self) -> Option<&Playlist> {
+		self.playlist_state
+			.selected()
+			.and_then(|i| self.playlists.get(i))
+	}
+
+	/// Smart playlists come after the saved ones in the playlists pane.
+	fn selected_smart_playlist(&self) -> Option<&SmartPlaylist> {
+		self.playlist_state
+			.selected()
+			.and_then(|i| i.checked_sub(self.playlists.len()))
+			.and_then(|i| self.config.smart_playlists.get(i))
+	}
+
+	pub fn selected_playlist_name(&self) -> Option<&str> {
+		match self.selected_playlist() {
+			Some(playlist) => Some(&playlist.name),
+			None => self.selected_smart_playlist().map(|p| p.name.as_str()),
+		}
+	}
+
+	/// Rows of the playlists pane.
+	fn playlist_count(&self) -> usize {
+		self.playlists.len() + self.config.smart_playlists.len()
+	}
+
+	/// Select a row of the playlists pane, filling a smart playlist from the library.
+	fn select_playlist(&mut self, i: Option<usize>) {
+		self.playlist_state.select(i);
+		self.entry_state.select(Some(0));
+		self.smart_tracks = match self.selected_smart_playlist() {
+			Some(smart) => smart.evaluate(&self.all_tracks, &self.all_albums),
+			None => Vec::new(),
+		};
+	}
+
+	fn playlist_len(&self) -> usize {
+		match self.selected_playlist() {
+			Some(playlist) => playlist.entries.len(),
+			None => self.smart_tracks.len(),
+		}
+	}
+
+	/// Entries of the selected playlist with the library track each one
+	/// points at, or `None` if the file is missing or outside the library.
+	pub fn playlist_rows(&self) -> Vec<(&playlists::Entry, Option<&load_album_and_track_lists::Track>)> {
+		let Some(playlist) = self.selected_playlist() else {
+			return Vec::new();
+		};
+		let by_path: HashMap<&str, &load_album_and_track_lists::Track> =
+			self.all_tracks.iter().map(|t| (t.path.as_str(), t)).collect();
+		playlist
+			.entries
+			.iter()
+			.map(|entry| (entry, by_path.get(entry.path.as_str()).copied()))
+			.collect()
+	}
+
+	/// The playable tracks of the selected playlist, in playlist order.
+	fn playlist_tracks(&self) -> Vec<load_album_and_track_lists::Track> {
+		if self.selected_smart_playlist().is_some() {
+			return self.smart_tracks.clone();
+		}
+		self.playlist_rows()
+			.into_iter()
+			.filter_map(|(_, track)| track.cloned())
+			.collect()
+	}
+
+	/// Replace the queue with the selected playlist and start playing it.
+	fn play_playlist(&mut self) {
+		let tracks = self.playlist_tracks();
+		if tracks.is_empty() {
+			return;
+		}
+		self.queue = tracks;
+		self.start_play_at(0);
+	}
+
+	pub fn open_new_playlist(&mut self) {
+		self.open_prompt(PromptKind::NewPlaylist, "");
+	}
+
+	pub fn open_new_smart_playlist(&mut self) {
+		self.open_prompt(PromptKind::NewSmartPlaylist, "");
+	}
+
+	/// Rename the selected playlist, or edit the rules of a smart one.
+	pub fn open_rename_playlist(&mut self) {
+		if !matches!(self.active_panel, ActivePanel::Playlists) {
+			return;
+		}
+		if let Some(name) = self.selected_playlist().map(|p| p.name.clone()) {
+			self.open_prompt(PromptKind::RenamePlaylist, &name);
+		} else if let Some(definition) = self.selected_smart_playlist().map(SmartPlaylist::definition) {
+			self.open_prompt(PromptKind::EditSmartPlaylist, &definition);
+		}
+	}
+
+	/// Add a smart playlist from its definition, or replace the one at
+	/// `editing`, and open it.
+	fn save_smart_playlist(&mut self, definition: &str, editing: Option<usize>) {
+		let smart = match SmartPlaylist::parse(definition) {
+			Ok(smart) => smart,
+			Err(e) => {
+				self.status = Some(e);
+				return;
+			}
+		};
+		let taken = self
+			.config
+			.smart_playlists
+			.iter()
+			.enumerate()
+			.any(|(i, p)| Some(i) != editing && p.name.eq_ignore_ascii_case(&smart.name));
+		if taken {
+			self.status = Some(format!("A smart playlist called \"{}\" already exists", smart.name));
+			return;
+		}
+		let name = smart.name.clone();
+		match editing.filter(|&i| i < self.config.smart_playlists.len()) {
+			Some(i) => self.config.smart_playlists[i] = smart,
+			None => self.config.smart_playlists.push(smart),
+		}
+		self.config.smart_playlists.sort_by_key(|p| p.name.to_lowercase());
+		self.config.save(&self.config_path);
+		let i = self.config.smart_playlists.iter().position(|p| p.name == name);
+		self.select_playlist(i.map(|i| self.playlists.len() + i));
+		self.status = Some(format!("{}: {}", name, track_count(self.smart_tracks.len())));
+	}
+
+	/// Create a playlist holding `tracks` and select it.
+	fn create_playlist(&mut self, name: &str, tracks: &[load_album_and_track_lists::Track]) {
+		let mut playlist = match Playlist::create(name) {
+			Ok(playlist) => playlist,
+			Err(e) => {
+				self.status = Some(e);
+				return;
+			}
+		};
+		self.status = Some(format!("Created playlist {}", playlist.name));
+		if !tracks.is_empty() {
+			playlist.entries = tracks.iter().map(playlists::Entry::from_track).collect();
+			self.status = Some(match playlist.save() {
+				Ok(()) => format!("Added {} to {}", track_count(tracks.len()), playlist.name),
+				Err(e) => format!("Couldn't save {}: {}", playlist.name, e),
+			});
+		}
+		let path = playlist.path.clone();
+		self.playlists.push(playlist);
+		self.sort_playlists(&path);
+	}
+
+	fn rename_playlist(&mut self, name: &str) {
+		let Some(i) = self.playlist_state.selected() else {
+			return;
+		};
+		let Some(playlist) = self.playlists.get_mut(i) else {
+			return;
+		};
+		if let Err(e) = playlist.rename(name) {
+			self.status = Some(format!("Couldn't rename {}: {}", playlist.name, e));
+			return;
+		}
+		let path = playlist.path.clone();
+		self.sort_playlists(&path);
+	}
+
+	/// Keep playlists sorted by name, with the one at `selected` still selected.
+	fn sort_playlists(&mut self, selected: &Path) {
+		self.playlists.sort_by_key(|p| p.name.to_lowercase());
+		let i = self.playlists.iter().position(|p| p.path == selected);
+		self.select_playlist(i);
+	}
+
+	/// Ask before deleting the selected playlist.
+	pub fn confirm_delete(&mut self) {
+		match self.active_panel {
+			ActivePanel::Playlists if self.selected_playlist_name().is_some() => {
+				self.popup = Some(Popup::DeletePlaylist);
+			}
+			ActivePanel::Entries => self.remove_entry(),
+			_ => {}
+		}
+	}
+
+	pub fn delete_playlist(&mut self) {
+		self.close_popup();
+		let Some(i) = self.playlist_state.selected() else {
+			return;
+		};
+		if let Some(smart) = i.checked_sub(self.playlists.len()) {
+			if smart >= self.config.smart_playlists.len() {
+				return;
+			}
+			let playlist = self.config.smart_playlists.remove(smart);
+			self.config.save(&self.config_path);
+			self.status = Some(format!("Deleted smart playlist {}", playlist.name));
+		} else {
+			if let Err(e) = self.playlists[i].delete() {
+				self.status = Some(format!("Couldn't delete {}: {}", self.playlists[i].name, e));
+				return;
+			}
+			let playlist = self.playlists.remove(i);
+			self.status = Some(format!("Deleted playlist {}", playlist.name));
+		}
+		self.select_playlist(Some(i.min(self.playlist_count().saturating_sub(1))));
+	}
+
+	/// Remove the selected entry from its playlist.
+	fn remove_entry(&mut self) {
+		let (Some(p), Some(i)) = (self.playlist_state.selected(), self.entry_state.selected()) else {
+			return;
+		};
+		let Some(playlist) = self.playlists.get_mut(p) else {
+			return;
+		};
+		if i >= playlist.entries.len() {
+			return;
+		}
+		playlist.entries.remove(i);
+		if let Err(e) = playlist.save() {
+			self.status = Some(format!("Couldn't save {}: {}", playlist.name, e));
+		}
+		self.entry_state
+			.select(Some(i.min(playlist.entries.len().saturating_sub(1))));
+	}
+
+	/// Move the selected playlist entry or queue track one row up (`-1`) or down (`1`).
+	pub fn move_selected(&mut self, offset: isize) {
+		match self.active_panel {
+			ActivePanel::Entries => self.move_entry(offset),
+			ActivePanel::Queue => {
+				let rows = self.target_rows();
+				let cursor = self
+					.queue_state
+					.selected()
+					.map(|c| match c.checked_add_signed(offset) {
+						Some(to) if rows.contains(&c) => to,
+						_ => c,
+					});
+				if !move_rows_by(&mut self.queue, &mut self.queue_index, &rows, offset) {
+					return;
+				}
+				self.marks.shift(offset);
+				self.queue_state.select(cursor);
+			}
+			_ => {}
+		}
+	}
+
+	fn move_entry(&mut self, offset: isize) {
+		let (Some(p), Some(i)) = (self.playlist_state.selected(), self.entry_state.selected()) else {
+			return;
+		};
+		let Some(playlist) = self.playlists.get_mut(p) else {
+			return;
+		};
+		let Some(target) = i.checked_add_signed(offset).filter(|t| *t < playlist.entries.len()) else {
+			return;
+		};
+		playlist.entries.swap(i, target);
+		if let Err(e) = playlist.save() {
+			self.status = Some(format!("Couldn't save {}: {}", playlist.name, e));
+		}
+		self.entry_state.select(Some(target));
+	}
+
+	/// Pick a playlist to add the selected tracks to.
+	pub fn open_add_to_playlist(&mut self) {
+		let tracks = self.selected_tracks();
+		self.clear_marks();
+		if tracks.is_empty() {
+			return;
+		}
+		self.playlist_pending = tracks;
+		self.popup = Some(Popup::AddToPlaylist);
+		self.popup_state.select(Some(0));
+	}
+
+	/// Add the pending tracks to the playlist picked in the popup; the first
+	/// row makes a new one.
+	pub fn add_to_selected_playlist(&mut self) {
+		let Some(row) = self.popup_state.selected() else {
+			return;
+		};
+		if row == 0 {
+			let pending = std::mem::take(&mut self.playlist_pending);
+			self.open_new_playlist();
+			self.playlist_pending = pending;
+			return;
+		}
+		let tracks = std::mem::take(&mut self.playlist_pending);
+		self.close_popup();
+		let Some(playlist) = self.playlists.get_mut(row - 1) else {
+			return;
+		};
+		playlist
+			.entries
+			.extend(tracks.iter().map(playlists::Entry::from_track));
+		self.status = Some(match playlist.save() {
+			Ok(()) => format!("Added {} to {}", track_count(tracks.len()), playlist.name),
+			Err(e) => format!("Couldn't save {}: {}", playlist.name, e),
+		});
+	}
+
+	// queue files
+
+	/// Pick a format and path style to save the queue as.
+	pub fn open_export_queue(&mut self) {
+		if self.queue.is_empty() {
+			self.status = Some(String::from("The queue is empty"));
+			return;
+		}
+		self.popup = Some(Popup::ExportQueue);
+		self.popup_state.select(Some(0));
+	}
+
+	/// Ask where to save the queue in the format picked in the popup.
+	pub fn choose_export(&mut self) {
+		let Some((format, relative)) = self
+			.popup_state
+			.selected()
+			.and_then(|i| export_choices().get(i).copied())
+		else {
+			return;
+		};
+		let path = Path::new(&load_album_and_track_lists::library_dir())
+			.join(format!("queue.{}", format.extension()));
+		self.open_prompt(
+			PromptKind::ExportQueue(format, relative),
+			&path.to_string_lossy(),
+		);
+	}
+
+	/// Save the queue to `path`. A known extension decides the format,
+	/// otherwise the one picked is used and its extension added. Existing
+	/// files are never overwritten; the prompt comes back to pick another name.
+	fn export_queue(&mut self, path: &str, format: playlists::Format, relative: bool) {
+		if path.is_empty() {
+			return;
+		}
+		let mut path = PathBuf::from(path);
+		let format = match playlists::Format::from_path(&path) {
+			Some(format) => format,
+			None => {
+				path.as_mut_os_string().push(format!(".{}", format.extension()));
+				format
 			}
+		};
+		if path.exists() {
+			self.status = Some(format!("{} already exists", path.display()));
+			self.open_prompt(
+				PromptKind::ExportQueue(format, relative),
+				&path.to_string_lossy(),
+			);
+			return;
 		}
+		let library_dir = load_album_and_track_lists::library_dir();
+		let entries: Vec<playlists::Entry> =
+			self.queue.iter().map(playlists::Entry::from_track).collect();
+		let relative_to = relative.then_some(Path::new(&library_dir));
+		self.status = Some(
+			match playlists::write_file(&path, &entries, format, relative_to) {
+				Ok(()) => format!(
+					"Saved {} to {}",
+					track_count(entries.len()),
+					path.display()
+				),
+				Err(e) => format!("Couldn't save {}: {}", path.display(), e),
+			},
+		);
 	}
 
-	// queue
+	pub fn open_import_queue(&mut self) {
+		self.open_prompt(PromptKind::ImportQueue, &load_album_and_track_lists::library_dir());
+	}
 
-	pub fn add_all_tracks_to_queue(&mut self) {
-		for i in self.tracks.clone() {
-			self.queue.push(i)
+	/// Add the tracks of the playlist file at `path` to the queue. Entries that
+	/// can't be found in the library are left out.
+	fn import_queue(&mut self, path: &str) {
+		if path.is_empty() {
+			return;
+		}
+		let path = Path::new(path);
+		let entries = match playlists::read_file(path) {
+			Ok(entries) => entries,
+			Err(e) => {
+				self.status = Some(format!("Couldn't read {}: {}", path.display(), e));
+				return;
+			}
+		};
+		let library_dir = load_album_and_track_lists::library_dir();
+		let tracks: Vec<load_album_and_track_lists::Track> = playlists::find_tracks(
+			&entries,
+			&self.all_tracks,
+			path.parent().unwrap_or(Path::new(".")),
+			Path::new(&library_dir),
+		)
+		.into_iter()
+		.flatten()
+		.cloned()
+		.collect();
+		let missing = entries.len() - tracks.len();
+		self.status = Some(if missing > 0 {
+			format!("Added {} to the queue, {} not found", track_count(tracks.len()), missing)
+		} else {
+			format!("Added {} to the queue", track_count(tracks.len()))
+		});
+		self.append_to_queue(tracks);
+	}
+
+	/// Add `tracks` to the end of the queue and start playing if nothing is.
+	fn append_to_queue(&mut self, mut tracks: Vec<load_album_and_track_lists::Track>) {
+		if tracks.is_empty() {
+			return;
 		}
+		let first_new = self.queue.len();
+		self.queue.append(&mut tracks);
 		self.queue_state
 			.select(Some(self.queue.len().saturating_sub(1)));
+		// after a stop the queue may already hold what was played
 		if self.player.current_track().is_none() {
-			self.start_play_at(0);
+			self.start_play_at(first_new);
 		}
 	}
 
-	pub fn clear_queue(&mut self) {
-		self.queue.clear();
-		self.queue_state.select(None);
-		self.queue_index = None;
+	// auto-DJ
+
+	pub fn toggle_auto_dj(&mut self) {
+		self.config.auto_dj = self.config.auto_dj.next();
+		self.config.save(&self.config_path);
+		self.status = Some(format!("Auto-DJ: {}", self.config.auto_dj.label()));
 	}
-	pub fn shuffle_queue(&mut self) {
-		let mut rng = rng();
-		self.queue.shuffle(&mut rng);
 
-		if !self.queue.is_empty() {
-			self.queue_state.select(Some(0));
-			self.queue_index = Some(0);
+	/// Add a track after the last one in the queue, if the auto-DJ is on and
+	/// finds one. Tracks in the queue or played lately are avoided.
+	fn auto_dj_extend(&mut self) {
+		let Some(last) = self.queue.last() else {
+			return;
+		};
+		let recent: HashSet<&str> = self
+			.history
+			.plays
+			.iter()
+			.rev()
+			.take(AUTO_DJ_RECENT)
+			.map(|p| p.path.as_str())
+			.chain(self.queue.iter().map(|t| t.path.as_str()))
+			.collect();
+		let favorites: HashSet<&str> = self
+			.all_tracks
+			.iter()
+			.filter(|t| t.favorite)
+			.chain(self.all_albums.iter().filter(|a| a.favorite).flat_map(|a| a.tracks.iter()))
+			.map(|t| t.path.as_str())
+			.collect();
+		let mode = self.config.auto_dj;
+		let Some(pick) = auto_dj::pick(
+			mode,
+			last,
+			&self.all_tracks,
+			&recent,
+			&self.config.auto_dj_exclude,
+			&favorites,
+		) else {
+			if mode == AutoDj::Favorites {
+				self.status = Some(String::from("Auto-DJ has no favorites to add"));
+			}
+			return;
+		};
+		let track = pick.track;
+		self.status = Some(if pick.random {
+			format!(
+				"Auto-DJ found nothing for {}, added {} - {} at random",
+				mode.label(),
+				track.artist,
+				track.track_name
+			)
 		} else {
-			self.queue_state.select(None);
-			self.queue_index = None;
-		}
+			format!("Auto-DJ added {} - {}", track.artist, track.track_name)
+		});
+		self.queue.push(track);
 	}
 
-	// sort
+	// history
 
-	pub fn toggle_sort(&mut self) {
-		self.sort_state = match self.sort_state {
-			SortState::AZ => SortState::ZA,
-			SortState::ZA => SortState::AZ,
-		};
-		self.sort_lists_with_favorites();
+	/// The play selected in the history popup, which lists the newest first.
+	pub fn selected_play(&self) -> Option<&Play> {
+		let i = self.popup_state.selected()?;
+		self.history.plays.iter().rev().nth(i)
 	}
 
-	pub fn sort_lists(&mut self) {
-		self.sort_lists_with_favorites();
+	/// Queue the library track of the selected play.
+	pub fn queue_history_track(&mut self) {
+		let Some(play) = self.selected_play() else {
+			return;
+		};
+		let track = self
+			.all_tracks
+			.iter()
+			.find(|t| t.path == play.path)
+			.or_else(|| {
+				self.all_tracks
+					.iter()
+					.find(|t| t.artist == play.artist && t.track_name == play.title)
+			})
+			.cloned();
+		match track {
+			Some(track) => {
+				self.status = Some(format!("Added {} - {} to the queue", track.artist, track.track_name));
+				self.append_to_queue(vec![track]);
+			}
+			None => self.status = Some(format!("{} is no longer in the library", play.title)),
+		}
 	}
 
-	fn sort_lists_with_favorites(&mut self) {
-		let sort_state = self.sort_state;
-
-		let sort_albums = |a: &load_album_and_track_lists::Album,
-		                   b: &load_album_and_track_lists::Album| {
-			match (a.favorite, b.favorite) {
-				(true, false) => std::cmp::Ordering::Less,
-				(false, true) => std::cmp::Ordering::Greater,
-				_ => match sort_state {
-					SortState::AZ => {
-						a.artist.to_lowercase()
-							.cmp(&b.artist.to_lowercase())
-					}
-					SortState::ZA => {
-						b.artist.to_lowercase()
-							.cmp(&a.artist.to_lowercase())
-					}
-				},
-			}
+	/// Queue the whole album of the selected play.
+	pub fn queue_history_album(&mut self) {
+		let Some(play) = self.selected_play() else {
+			return;
 		};
-
-		let sort_tracks = |a: &load_album_and_track_lists::Track,
-		                   b: &load_album_and_track_lists::Track| {
-			match (a.favorite, b.favorite) {
-				(true, false) => std::cmp::Ordering::Less,
-				(false, true) => std::cmp::Ordering::Greater,
-				_ => match sort_state {
-					SortState::AZ => a
-						.track_name
-						.to_lowercase()
-						.cmp(&b.track_name.to_lowercase()),
-					SortState::ZA => b
-						.track_name
-						.to_lowercase()
-						.cmp(&a.track_name.to_lowercase()),
-				},
+		let album = self
+			.all_albums
+			.iter()
+			.find(|a| a.tracks.iter().any(|t| t.path == play.path))
+			.or_else(|| {
+				self.all_albums.iter().find(|a| {
+					a.tracks
+						.iter()
+						.any(|t| t.artist == play.artist && t.album == play.album)
+				})
+			})
+			.cloned();
+		match album {
+			Some(album) => {
+				self.status = Some(format!("Added {} to the queue", album.name));
+				self.append_to_queue(album.tracks);
 			}
+			None => self.status = Some(format!("{} is no longer in the library", play.album)),
+		}
+	}
+
+	/// Record the playing track in the history, as skipped if it hasn't
+	/// played to the end. Does nothing if it was already recorded.
+	pub fn finish_play(&mut self) {
+		let Some(started) = self.play_started.take() else {
+			return;
 		};
+		if let Some(track) = &self.player.current_track {
+			let skipped = !self.player.sink.empty();
+			let listened = self.player.position().as_secs();
+			let identity = history::identity(&track.artist, &track.album, &track.track_name);
+			self.history.record(Play::new(track, started, listened, skipped));
+			for t in self
+				.library_tracks_mut()
+				.filter(|t| history::identity(&t.artist, &t.album, &t.track_name) == identity)
+			{
+				let mut stats = PlayStats::of(t);
+				stats.add(started, skipped);
+				stats.apply(t);
+			}
+		}
+	}
 
-		self.albums.sort_by(sort_albums);
-		self.tracks.sort_by(sort_tracks);
+	pub fn toggle_play_stats(&mut self) {
+		self.config.toggle_play_stats();
+		self.config.save(&self.config_path);
+	}
+
+	/// Set the play and skip counts of the library tracks from the history.
+	pub fn apply_play_stats(&mut self) {
+		let stats = self.history.play_stats();
+		for track in self.library_tracks_mut() {
+			stats
+				.get(&history::identity(&track.artist, &track.album, &track.track_name))
+				.copied()
+				.unwrap_or_default()
+				.apply(track);
+		}
+	}
+
+	/// Every copy of the library's tracks, including those inside albums.
+	fn library_tracks_mut(&mut self) -> impl Iterator<Item = &mut load_album_and_track_lists::Track> {
+		self.tracks
+			.iter_mut()
+			.chain(self.all_tracks.iter_mut())
+			.chain(self.albums.iter_mut().chain(self.all_albums.iter_mut()).flat_map(|a| a.tracks.iter_mut()))
 	}
 
 	// find
@@ -507,70 +2528,151 @@ impl App {
 	}
 
 	pub fn find_albums(&mut self) {
-		if self.find_term.is_empty() {
-			self.albums = self.all_albums.clone();
-		} else {
-			let term = self.find_term.to_lowercase();
-			self.albums = self
-				.all_albums
-				.iter()
-				.filter(|album| {
-					album.name.to_lowercase().contains(&term)
-						|| album.artist.to_lowercase().contains(&term)
-				})
-				.cloned()
-				.collect();
-		}
-		let sort_state = self.sort_state;
-		self.albums.sort_by(|a, b| match (a.favorite, b.favorite) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => match sort_state {
-				SortState::AZ => {
-					a.artist.to_lowercase().cmp(&b.artist.to_lowercase())
-				}
-				SortState::ZA => {
-					b.artist.to_lowercase().cmp(&a.artist.to_lowercase())
-				}
-			},
-		});
+		self.clear_marks();
+		let query = Query::parse(&self.find_term);
+		self.albums = self
+			.all_albums
+			.iter()
+			.filter(|album| query.matches_album(album))
+			.cloned()
+			.collect();
+		self.albums.sort_by(album_order(self.sort_state, self.sort_key));
 
 		self.album_state.select(Some(0));
 	}
 	pub fn find_tracks(&mut self) {
-		if self.find_term.is_empty() {
-			self.tracks = self.all_tracks.clone();
-		} else {
-			let term = self.find_term.to_lowercase();
-			self.tracks = self
-				.all_tracks
-				.iter()
-				.filter(|track| {
-					track.track_name.to_lowercase().contains(&term)
-						|| track.artist.to_lowercase().contains(&term)
-				})
-				.cloned()
-				.collect();
-		}
+		self.clear_marks();
+		let query = Query::parse(&self.find_term);
+		self.tracks = self
+			.all_tracks
+			.iter()
+			.filter(|track| query.matches_track(track))
+			.cloned()
+			.collect();
+		self.tracks.sort_by(track_order(self.sort_state, self.sort_key));
 
-		let sort_state = self.sort_state;
+		self.track_state.select(Some(0));
+		self.cascade();
+	}
 
-		self.tracks.sort_by(|a, b| match (a.favorite, b.favorite) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => match sort_state {
-				SortState::AZ => a
-					.track_name
-					.to_lowercase()
-					.cmp(&b.track_name.to_lowercase()),
-				SortState::ZA => b
-					.track_name
-					.to_lowercase()
-					.cmp(&a.track_name.to_lowercase()),
-			},
-		});
+	/// Put a `key:value` filter from the quick filters popup into the find field.
+	pub fn apply_selected_filter(&mut self) {
+		let Some(row) = self
+			.popup_state
+			.selected()
+			.and_then(|i| self.filter_rows.get(i))
+			.cloned()
+		else {
+			return;
+		};
+		self.find_term = match &row.filter {
+			Some((key, value)) => query::with_filter(&self.find_term, key, value),
+			None => query::without_filters(&self.find_term),
+		};
+		self.input = self.find_term.clone();
+		self.find_char_index = self.input.chars().count();
+		self.close_popup();
+		self.find_albums();
+		self.find_tracks();
+	}
 
-		self.track_state.select(Some(0));
+	/// Genres, decades, release types, formats and qualities in the library,
+	/// with album counts.
+	fn build_filter_rows(&self) -> Vec<FilterRow> {
+		let mut rows = vec![FilterRow {
+			label: String::from("Clear filters"),
+			filter: None,
+		}];
+		let count_rows = |key: &'static str,
+		                  label: &str,
+		                  values: Vec<String>,
+		                  by_count: bool|
+		 -> Vec<FilterRow> {
+			let mut counts: Vec<(String, usize)> = Vec::new();
+			for value in values {
+				match counts.iter_mut().find(|(v, _)| v.eq_ignore_ascii_case(&value)) {
+					Some((_, n)) => *n += 1,
+					None => counts.push((value, 1)),
+				}
+			}
+			if by_count {
+				counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+			} else {
+				counts.sort();
+			}
+			counts
+				.into_iter()
+				.map(|(value, n)| FilterRow {
+					label: format!("{}: {} ({})", label, value, n),
+					filter: Some((key, value)),
+				})
+				.collect()
+		};
+		let albums = &self.all_albums;
+		rows.extend(count_rows(
+			"genre",
+			"Genre",
+			albums.iter().flat_map(|a| a.genres.clone()).collect(),
+			true,
+		));
+		rows.extend(count_rows(
+			"year",
+			"Decade",
+			albums
+				.iter()
+				.filter(|a| a.year > 0)
+				.map(|a| format!("{}s", a.year / 10 * 10))
+				.collect(),
+			false,
+		));
+		rows.extend(count_rows(
+			"type",
+			"Type",
+			albums
+				.iter()
+				.flat_map(|a| {
+					a.release_type
+						.split([';', '/'])
+						.map(|t| t.trim().to_string())
+						.filter(|t| !t.is_empty())
+						.collect::<Vec<_>>()
+				})
+				.collect(),
+			true,
+		));
+		// each album counts once per format and quality it has tracks in
+		let per_album = |album: &load_album_and_track_lists::Album,
+		                 value: &dyn Fn(&load_album_and_track_lists::Track) -> Option<String>| {
+			let mut values: Vec<String> = album.tracks.iter().filter_map(value).collect();
+			values.sort();
+			values.dedup();
+			values
+		};
+		rows.extend(count_rows(
+			"format",
+			"Format",
+			albums
+				.iter()
+				.flat_map(|a| per_album(a, &|t| Some(t.audio.format.clone())))
+				.collect(),
+			true,
+		));
+		rows.extend(count_rows(
+			"is",
+			"Quality",
+			albums
+				.iter()
+				.flat_map(|a| {
+					let mut values = per_album(a, &|t| {
+						Some(String::from(if t.audio.is_lossless() { "lossless" } else { "lossy" }))
+					});
+					values.extend(per_album(a, &|t| t.audio.is_hires().then(|| String::from("hires"))));
+					values
+				})
+				.collect(),
+			true,
+		));
+		rows
 	}
 
 	pub fn clear_find(&mut self) {
@@ -597,39 +2699,91 @@ impl App {
 			}
 		}
 
-		let sort_state = self.sort_state;
-		albums.sort_by(|a, b| match (a.favorite, b.favorite) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => match sort_state {
-				SortState::AZ => {
-					a.artist.to_lowercase().cmp(&b.artist.to_lowercase())
-				}
-				SortState::ZA => {
-					b.artist.to_lowercase().cmp(&a.artist.to_lowercase())
-				}
-			},
-		});
-		tracks.sort_by(|a, b| match (a.favorite, b.favorite) {
-			(true, false) => std::cmp::Ordering::Less,
-			(false, true) => std::cmp::Ordering::Greater,
-			_ => match sort_state {
-				SortState::AZ => a
-					.track_name
-					.to_lowercase()
-					.cmp(&b.track_name.to_lowercase()),
-				SortState::ZA => b
-					.track_name
-					.to_lowercase()
-					.cmp(&a.track_name.to_lowercase()),
-			},
-		});
+		albums.sort_by(album_order(self.sort_state, self.sort_key));
+		tracks.sort_by(track_order(self.sort_state, self.sort_key));
 
 		self.albums = albums;
 		self.tracks = tracks;
 
 		self.album_state.select(Some(0));
 		self.track_state.select(Some(0));
+		self.cascade();
+	}
+
+	// ratings
+	/// Ratings set in stim win over the ones read from the tags.
+	pub fn apply_ratings(&mut self) {
+		let config = &self.config;
+		for album in self.albums.iter_mut().chain(self.all_albums.iter_mut()) {
+			album.rating = config.album_rating(&album.artist, &album.name).unwrap_or_default();
+		}
+		let config = self.config.clone();
+		for track in self.library_tracks_mut() {
+			if let Some(stars) = config.track_rating(&track.artist, &track.track_name) {
+				track.rating = stars;
+			}
+		}
+	}
+
+	/// Rate the selected (or marked) albums or tracks, 0 to remove the rating.
+	pub fn rate(&mut self, stars: u8) {
+		let stars = stars.min(ratings::MAX_STARS);
+		let rated = match self.active_panel {
+			ActivePanel::Albums => {
+				let rows = self.target_rows();
+				let albums: Vec<(String, String)> = rows
+					.into_iter()
+					.filter_map(|i| self.albums.get(i))
+					.map(|a| (a.artist.clone(), a.name.clone()))
+					.collect();
+				for (artist, name) in &albums {
+					self.config.set_album_rating(artist, name, stars);
+				}
+				for album in self.albums.iter_mut().chain(self.all_albums.iter_mut()) {
+					if albums.iter().any(|(artist, name)| *artist == album.artist && *name == album.name) {
+						album.rating = stars;
+					}
+				}
+				match albums.len() {
+					1 => String::from("1 album"),
+					n => format!("{} albums", n),
+				}
+			}
+			ActivePanel::Tracks | ActivePanel::Queue | ActivePanel::Entries => {
+				let tracks = self.selected_tracks();
+				let mut failed = Vec::new();
+				for track in &tracks {
+					self.config.set_track_rating(&track.artist, &track.track_name, stars);
+					if self.config.write_rating_tags
+						&& let Err(e) = ratings::write(&track.path, stars)
+					{
+						failed.push(format!("{}: {}", track.track_name, e));
+					}
+				}
+				for t in self.library_tracks_mut() {
+					if tracks.iter().any(|r| r.artist == t.artist && r.track_name == t.track_name) {
+						t.rating = stars;
+					}
+				}
+				if !failed.is_empty() {
+					self.clear_marks();
+					self.config.save(&self.config_path);
+					self.status = Some(format!("Couldn't write the rating to {}", failed.join(", ")));
+					return;
+				}
+				track_count(tracks.len())
+			}
+			_ => return,
+		};
+		self.clear_marks();
+		self.config.save(&self.config_path);
+		if self.sort_key == SortKey::Rating {
+			self.sort_lists_with_favorites();
+		}
+		self.status = Some(match stars {
+			0 => format!("Removed the rating of {}", rated),
+			_ => format!("Rated {} {}", rated, ratings::stars(stars)),
+		});
 	}
 
 	// favorite
@@ -689,12 +2843,25 @@ impl App {
 		self.config.save(&self.config_path);
 	}
 
+	/// Favorite the selected (or marked) albums or tracks, or unfavorite them
+	/// if they all are already.
 	pub fn toggle_favorite(&mut self) {
+		let rows = self.target_rows();
+		self.clear_marks();
+		if rows.is_empty() {
+			return;
+		}
 		match self.active_panel {
 			ActivePanel::Albums => {
-				if let Some(i) = self.album_state.selected() {
-					let album = &mut self.albums[i];
-					album.favorite = !album.favorite;
+				let favorite = !rows
+					.iter()
+					.filter_map(|&i| self.albums.get(i))
+					.all(|a| a.favorite);
+				for i in rows {
+					let Some(album) = self.albums.get_mut(i) else {
+						continue;
+					};
+					album.favorite = favorite;
 
 					// update AppConfig
 					if album.favorite {
@@ -714,15 +2881,21 @@ impl App {
 					}) {
 						a.favorite = album.favorite;
 					}
-
-					self.sort_lists_with_favorites();
-					self.config.save(&self.config_path); // persist immediately
 				}
+
+				self.sort_lists_with_favorites();
+				self.config.save(&self.config_path); // persist immediately
 			}
 			ActivePanel::Tracks => {
-				if let Some(i) = self.track_state.selected() {
-					let track = &mut self.tracks[i];
-					track.favorite = !track.favorite;
+				let favorite = !rows
+					.iter()
+					.filter_map(|&i| self.tracks.get(i))
+					.all(|t| t.favorite);
+				for i in rows {
+					let Some(track) = self.tracks.get_mut(i) else {
+						continue;
+					};
+					track.favorite = favorite;
 
 					// update AppConfig
 					if track.favorite {
@@ -744,12 +2917,16 @@ impl App {
 					}) {
 						t.favorite = track.favorite;
 					}
-
-					self.sort_lists_with_favorites();
-					self.config.save(&self.config_path); // persist immediately
 				}
+
+				self.sort_lists_with_favorites();
+				self.config.save(&self.config_path); // persist immediately
 			}
-			ActivePanel::Queue => {}
+			ActivePanel::Artists
+			| ActivePanel::Folders
+			| ActivePanel::Playlists
+			| ActivePanel::Entries
+			| ActivePanel::Queue => {}
 		}
 	}
 
@@ -759,19 +2936,35 @@ impl App {
 		if index >= self.queue.len() {
 			return;
 		}
+		self.finish_play();
+		self.stopped = false;
 		self.queue_index = Some(index);
 		self.queue_state.select(Some(index));
 		let track = self.queue[index].clone();
 		self.player.load_track(track);
+		self.play_started = Some(history::now());
+	}
+
+	/// Stop playing and forget the track, e.g. when it was taken out of the queue.
+	fn stop(&mut self) {
+		self.finish_play();
+		self.player.sink.stop();
+		self.player.current_track = None;
+		self.queue_index = None;
+		self.stopped = true;
 	}
 
 	pub fn load_next_track_if_current_ends(&mut self) {
 		if self.player.sink.empty() {
+			self.finish_play();
 			if self.queue.is_empty() {
 				self.queue_index = None;
 				self.queue_state.select(None);
 				return;
 			}
+			if self.queue_index.is_some_and(|i| i + 1 == self.queue.len()) {
+				self.auto_dj_extend();
+			}
 			match self.queue_index {
 				Some(i) if i + 1 < self.queue.len() => {
 					self.start_play_at(i + 1);
@@ -781,27 +2974,33 @@ impl App {
 					self.queue_index = None;
 					self.queue_state.select(None);
 				}
-				None => {
+				None if !self.stopped => {
 					// nothing playing but queue present -> start first
 					self.start_play_at(0);
 				}
+				None => {}
 			}
 		}
 	}
 
 	pub fn next_track(&mut self) {
 		if self.queue.is_empty() {
+			self.finish_play();
 			self.player.sink.pause();
 			self.queue_index = None;
 			self.queue_state.select(None);
 			return;
 		}
+		if self.queue_index.is_some_and(|i| i + 1 == self.queue.len()) {
+			self.auto_dj_extend();
+		}
 		match self.queue_index {
 			Some(i) if i + 1 < self.queue.len() => {
 				self.start_play_at(i + 1);
 			}
 			Some(_) => {
 				// at end of queue
+				self.finish_play();
 				self.player.sink.pause();
 				self.queue_index = None;
 				self.queue_state.select(None);
@@ -837,11 +3036,7 @@ impl App {
 			let elapsed = self.player.position().as_secs() as usize;
 			let total = track.length as usize;
 			let width = player_chunk.width as usize;
-			let progress = if total > 0 {
-				(elapsed * width) / total
-			} else {
-				0
-			};
+			let progress = (elapsed * width).checked_div(total).unwrap_or(0);
 			let bar = format!(
 				"{}{}",
 				"█".repeat(progress),
@@ -868,3 +3063,329 @@ impl App {
 		}
 	}
 }
+
+/// Favorites first, then by `key` in the `direction`.
+fn album_order(
+	direction: SortState,
+	key: SortKey,
+) -> impl Fn(&load_album_and_track_lists::Album, &load_album_and_track_lists::Album) -> Ordering {
+	move |a, b| match (a.favorite, b.favorite) {
+		(true, false) => Ordering::Less,
+		(false, true) => Ordering::Greater,
+		_ => {
+			let by_name = || {
+				a.artist
+					.to_lowercase()
+					.cmp(&b.artist.to_lowercase())
+					.then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+			};
+			let order = match key {
+				SortKey::Name => by_name(),
+				SortKey::Year => year_key(a.year).cmp(&year_key(b.year)).then_with(by_name),
+				SortKey::Rating => b.rating.cmp(&a.rating).then_with(by_name),
+				SortKey::Plays | SortKey::Skips | SortKey::LastPlayed => {
+					let (a_stats, b_stats) = (album_stats(a), album_stats(b));
+					let (a_key, b_key) = match key {
+						SortKey::Plays => (a_stats.plays as u64, b_stats.plays as u64),
+						SortKey::Skips => (a_stats.skips as u64, b_stats.skips as u64),
+						_ => (a_stats.last_played, b_stats.last_played),
+					};
+					b_key.cmp(&a_key).then_with(by_name)
+				}
+			};
+			match direction {
+				SortState::AZ => order,
+				SortState::ZA => order.reverse(),
+			}
+		}
+	}
+}
+
+/// Favorites first, then by `key` in the `direction`.
+fn track_order(
+	direction: SortState,
+	key: SortKey,
+) -> impl Fn(&load_album_and_track_lists::Track, &load_album_and_track_lists::Track) -> Ordering {
+	move |a, b| match (a.favorite, b.favorite) {
+		(true, false) => Ordering::Less,
+		(false, true) => Ordering::Greater,
+		_ => {
+			let by_title = || a.track_name.to_lowercase().cmp(&b.track_name.to_lowercase());
+			let order = match key {
+				SortKey::Name => by_title(),
+				SortKey::Year => year_key(a.year).cmp(&year_key(b.year)).then_with(by_title),
+				SortKey::Rating => b.rating.cmp(&a.rating).then_with(by_title),
+				SortKey::Plays => b.plays.cmp(&a.plays).then_with(by_title),
+				SortKey::Skips => b.skips.cmp(&a.skips).then_with(by_title),
+				SortKey::LastPlayed => b.last_played.cmp(&a.last_played).then_with(by_title),
+			};
+			match direction {
+				SortState::AZ => order,
+				SortState::ZA => order.reverse(),
+			}
+		}
+	}
+}
+
+/// The plays and skips of all the tracks of an album, and when any was last played.
+fn album_stats(album: &load_album_and_track_lists::Album) -> PlayStats {
+	album.tracks.iter().fold(PlayStats::default(), |total, t| PlayStats {
+		plays: total.plays + t.plays,
+		skips: total.skips + t.skips,
+		last_played: total.last_played.max(t.last_played),
+	})
+}
+
+/// Unknown years (0) sort after every known one.
+fn year_key(year: i32) -> i32 {
+	if year > 0 { year } else { i32::MAX }
+}
+
+// queue rows; `playing` follows the playing row through every change
+
+/// Insert `items` at `at`, or at the end if `at` is past it.
+fn insert_rows<T>(rows: &mut Vec<T>, playing: &mut Option<usize>, at: usize, items: Vec<T>) {
+	let at = at.min(rows.len());
+	if let Some(c) = *playing
+		&& at <= c
+	{
+		*playing = Some(c + items.len());
+	}
+	rows.splice(at..at, items);
+}
+
+/// Remove row `i`. Returns whether it was the playing one, which leaves
+/// nothing playing.
+fn remove_row<T>(rows: &mut Vec<T>, playing: &mut Option<usize>, i: usize) -> bool {
+	rows.remove(i);
+	match *playing {
+		Some(c) if c > i => {
+			*playing = Some(c - 1);
+			false
+		}
+		Some(c) if c == i => {
+			*playing = None;
+			true
+		}
+		_ => false,
+	}
+}
+
+/// Move row `from` to `to`.
+fn move_row<T>(rows: &mut Vec<T>, playing: &mut Option<usize>, from: usize, to: usize) {
+	if from >= rows.len() || to >= rows.len() {
+		return;
+	}
+	let row = rows.remove(from);
+	rows.insert(to, row);
+	*playing = playing.map(|c| match c {
+		c if c == from => to,
+		c if from < c && to >= c => c - 1,
+		c if from > c && to <= c => c + 1,
+		c => c,
+	});
+}
+
+/// Move the rows at `indices` (ascending) to the end, keeping their order.
+fn move_rows_to_end<T>(rows: &mut Vec<T>, playing: &mut Option<usize>, indices: &[usize]) {
+	let last = rows.len().saturating_sub(1);
+	// every move takes a row out from before the next one
+	for (moved, i) in indices.iter().enumerate() {
+		move_row(rows, playing, i - moved, last);
+	}
+}
+
+/// Move the rows at `indices` (ascending) `offset` rows up or down together.
+/// Returns `false`, without moving anything, if one would leave the list.
+fn move_rows_by<T>(rows: &mut Vec<T>, playing: &mut Option<usize>, indices: &[usize], offset: isize) -> bool {
+	let fits = match offset {
+		..0 => indices.first().and_then(|i| i.checked_add_signed(offset)).is_some(),
+		_ => indices
+			.last()
+			.and_then(|i| i.checked_add_signed(offset))
+			.is_some_and(|i| i < rows.len()),
+	};
+	if !fits {
+		return false;
+	}
+	// move the row nearest the edge first so marked rows don't swap places
+	let order: Vec<usize> = if offset < 0 {
+		indices.to_vec()
+	} else {
+		indices.iter().rev().copied().collect()
+	};
+	for i in order {
+		if let Some(to) = i.checked_add_signed(offset) {
+			move_row(rows, playing, i, to);
+		}
+	}
+	true
+}
+
+/// The rows of the save queue popup: every format with absolute and with
+/// library-relative paths.
+pub fn export_choices() -> Vec<(playlists::Format, bool)> {
+	playlists::Format::ALL
+		.into_iter()
+		.flat_map(|format| [(format, false), (format, true)])
+		.collect()
+}
+
+/// "1 track" or "n tracks".
+fn track_count(n: usize) -> String {
+	if n == 1 { String::from("1 track") } else { format!("{} tracks", n) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rows(s: &str) -> Vec<char> {
+		s.chars().collect()
+	}
+
+	fn text(rows: &[char]) -> String {
+		rows.iter().collect()
+	}
+
+	#[test]
+	fn inserting_before_the_playing_row_shifts_it() {
+		let (mut queue, mut playing) = (rows("abcd"), Some(2));
+		insert_rows(&mut queue, &mut playing, 1, rows("xy"));
+		assert_eq!(text(&queue), "axybcd");
+		assert_eq!(playing, Some(4));
+		// at the playing row itself the new rows go before it
+		insert_rows(&mut queue, &mut playing, 4, rows("z"));
+		assert_eq!(text(&queue), "axybzcd");
+		assert_eq!(playing, Some(5));
+	}
+
+	#[test]
+	fn inserting_after_the_playing_row_leaves_it() {
+		let (mut queue, mut playing) = (rows("abcd"), Some(2));
+		// "play next"
+		insert_rows(&mut queue, &mut playing, 3, rows("x"));
+		assert_eq!(text(&queue), "abcxd");
+		assert_eq!(playing, Some(2));
+		insert_rows(&mut queue, &mut playing, 99, rows("yz"));
+		assert_eq!(text(&queue), "abcxdyz");
+		assert_eq!(playing, Some(2));
+
+		let (mut queue, mut playing) = (rows("ab"), None);
+		insert_rows(&mut queue, &mut playing, 0, rows("x"));
+		assert_eq!(text(&queue), "xab");
+		assert_eq!(playing, None);
+	}
+
+	#[test]
+	fn removing_rows_around_the_playing_row() {
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		assert!(!remove_row(&mut queue, &mut playing, 4));
+		assert_eq!(playing, Some(2));
+		assert!(!remove_row(&mut queue, &mut playing, 0));
+		assert_eq!(text(&queue), "bcd");
+		assert_eq!(playing, Some(1));
+		assert!(remove_row(&mut queue, &mut playing, 1));
+		assert_eq!(text(&queue), "bd");
+		assert_eq!(playing, None);
+
+		// the last row playing: nothing is left to point at
+		let (mut queue, mut playing) = (rows("ab"), Some(1));
+		assert!(remove_row(&mut queue, &mut playing, 1));
+		assert_eq!(text(&queue), "a");
+		assert_eq!(playing, None);
+	}
+
+	#[test]
+	fn moving_single_rows_past_the_playing_row() {
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		move_row(&mut queue, &mut playing, 0, 4);
+		assert_eq!(text(&queue), "bcdea");
+		assert_eq!(playing, Some(1));
+		move_row(&mut queue, &mut playing, 3, 0);
+		assert_eq!(text(&queue), "ebcda");
+		assert_eq!(playing, Some(2));
+		// the playing row itself
+		move_row(&mut queue, &mut playing, 2, 4);
+		assert_eq!(text(&queue), "ebdac");
+		assert_eq!(playing, Some(4));
+		// out of range does nothing
+		move_row(&mut queue, &mut playing, 5, 0);
+		assert_eq!(text(&queue), "ebdac");
+		assert_eq!(playing, Some(4));
+	}
+
+	#[test]
+	fn moving_rows_to_the_end() {
+		let (mut queue, mut playing) = (rows("abcde"), Some(1));
+		move_rows_to_end(&mut queue, &mut playing, &[0, 2]);
+		assert_eq!(text(&queue), "bdeac");
+		assert_eq!(playing, Some(0));
+
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		move_rows_to_end(&mut queue, &mut playing, &[1, 2, 3]);
+		assert_eq!(text(&queue), "aebcd");
+		assert_eq!(playing, Some(3));
+		assert_eq!(queue[playing.unwrap()], 'c');
+	}
+
+	#[test]
+	fn moving_several_rows_across_the_playing_row() {
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		assert!(move_rows_by(&mut queue, &mut playing, &[1, 3], -1));
+		assert_eq!(text(&queue), "badce");
+		assert_eq!(playing, Some(3));
+
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		assert!(move_rows_by(&mut queue, &mut playing, &[0, 1], 1));
+		assert_eq!(text(&queue), "cabde");
+		assert_eq!(playing, Some(0));
+
+		// the playing row moving along with others
+		let (mut queue, mut playing) = (rows("abcde"), Some(2));
+		assert!(move_rows_by(&mut queue, &mut playing, &[2, 3], 1));
+		assert_eq!(text(&queue), "abecd");
+		assert_eq!(playing, Some(3));
+	}
+
+	#[test]
+	fn rows_that_would_leave_the_queue_stay_put() {
+		let (mut queue, mut playing) = (rows("abc"), Some(1));
+		assert!(!move_rows_by(&mut queue, &mut playing, &[0, 2], -1));
+		assert!(!move_rows_by(&mut queue, &mut playing, &[1, 2], 1));
+		assert!(!move_rows_by(&mut queue, &mut playing, &[], 1));
+		assert_eq!(text(&queue), "abc");
+		assert_eq!(playing, Some(1));
+	}
+
+	fn album_track(artist: &str, album_artist: &str, album: &str) -> load_album_and_track_lists::Track {
+		let mut track = load_album_and_track_lists::Track::new(format!("{}/{}", artist, album));
+		track.artist = artist.to_string();
+		track.album_artist = album_artist.to_string();
+		track.album = album.to_string();
+		track
+	}
+
+	#[test]
+	fn album_entries_under_a_track_artist_move_to_the_album_artist() {
+		let albums = load_album_and_track_lists::create_album_list(vec![
+			album_track("Guest", "Various Artists", "Hits"),
+			album_track("Host", "Various Artists", "Hits"),
+			album_track("Guest", "Guest", "Solo"),
+			album_track("Host", "Host", "Solo"),
+		])
+		.unwrap();
+		let mut config = AppConfig::default();
+		config.add_album_favorite(String::from("Guest"), String::from("Hits"));
+		config.set_album_rating("Host", "Hits", 4);
+		// another album still goes by ("Host", "Solo")
+		config.add_album_favorite(String::from("Host"), String::from("Solo"));
+		assert!(config.migrate_album_keys(&albums));
+		assert!(config.is_album_favorite("Various Artists", "Hits"));
+		assert!(!config.is_album_favorite("Guest", "Hits"));
+		assert_eq!(config.album_rating("Various Artists", "Hits"), Some(4));
+		assert_eq!(config.album_rating("Host", "Hits"), None);
+		assert!(config.is_album_favorite("Host", "Solo"));
+		assert!(!config.migrate_album_keys(&albums));
+	}
+}
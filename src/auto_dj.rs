@@ -0,0 +1,108 @@
+use crate::load_album_and_track_lists::Track;
+use crate::query::Query;
+use rand::rng;
+use rand::seq::IndexedRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What gets added when the queue runs out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoDj {
+	#[default]
+	Off,
+	Random,
+	Artist, // more by the artist of the last track
+	Genre,  // sharing a genre with the last track
+	Era,    // from the same decade as the last track
+	Favorites,
+}
+
+impl AutoDj {
+	pub fn next(self) -> Self {
+		match self {
+			AutoDj::Off => AutoDj::Random,
+			AutoDj::Random => AutoDj::Artist,
+			AutoDj::Artist => AutoDj::Genre,
+			AutoDj::Genre => AutoDj::Era,
+			AutoDj::Era => AutoDj::Favorites,
+			AutoDj::Favorites => AutoDj::Off,
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			AutoDj::Off => "off",
+			AutoDj::Random => "random",
+			AutoDj::Artist => "same artist",
+			AutoDj::Genre => "same genre",
+			AutoDj::Era => "same era",
+			AutoDj::Favorites => "favorites",
+		}
+	}
+
+	/// Whether `track` may follow `last`. `favorites` holds the paths of
+	/// favorite tracks and of the tracks of favorite albums.
+	fn fits(&self, track: &Track, last: &Track, favorites: &HashSet<&str>) -> bool {
+		match self {
+			AutoDj::Off => false,
+			AutoDj::Random => true,
+			AutoDj::Artist => track.artist.eq_ignore_ascii_case(&last.artist),
+			AutoDj::Genre => track.genres.iter().any(|g| {
+				last.genres.iter().any(|l| l.eq_ignore_ascii_case(g))
+			}),
+			AutoDj::Era => last.year > 0 && track.year / 10 == last.year / 10,
+			AutoDj::Favorites => favorites.contains(track.path.as_str()),
+		}
+	}
+}
+
+/// A track chosen by `pick`.
+pub struct Pick {
+	pub track: Track,
+	pub random: bool, // nothing fit the mode, so it's any track from the library
+}
+
+/// Pick a track to play after `last`. Tracks matching any of the `exclude`
+/// rules are never picked, and `recent` ones only if nothing else fits the
+/// mode. If nothing fits at all, any track from the library will do, except
+/// in favorites mode, which only ever plays favorites.
+pub fn pick(
+	mode: AutoDj,
+	last: &Track,
+	library: &[Track],
+	recent: &HashSet<&str>,
+	exclude: &[String],
+	favorites: &HashSet<&str>,
+) -> Option<Pick> {
+	if mode == AutoDj::Off {
+		return None;
+	}
+	let exclude: Vec<Query> = exclude
+		.iter()
+		.map(|rules| Query::parse(rules))
+		.filter(|query| !query.is_empty())
+		.collect();
+	let allowed: Vec<&Track> = library
+		.iter()
+		.filter(|t| t.path != last.path && !exclude.iter().any(|q| q.matches_track(t)))
+		.collect();
+	let mut passes = vec![(mode, true), (mode, false)];
+	if mode != AutoDj::Favorites {
+		passes.extend([(AutoDj::Random, true), (AutoDj::Random, false)]);
+	}
+	for (pass, avoid_recent) in passes {
+		let candidates: Vec<&Track> = allowed
+			.iter()
+			.filter(|t| pass.fits(t, last, favorites) && !(avoid_recent && recent.contains(t.path.as_str())))
+			.copied()
+			.collect();
+		if let Some(track) = candidates.choose(&mut rng()) {
+			return Some(Pick {
+				track: (*track).clone(),
+				random: pass != mode,
+			});
+		}
+	}
+	None
+}
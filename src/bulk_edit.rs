@@ -0,0 +1,121 @@
+use crate::load_album_and_track_lists::Track;
+use crate::tag_editor::{self, TagField};
+use std::collections::HashMap;
+
+/// One changed field of one file.
+#[derive(Debug, Clone)]
+pub struct BulkChange {
+	pub path: String,
+	pub field: TagField,
+	pub old: String,
+	pub new: String,
+}
+
+const HEADER_COMMENT: &str = "\
+# stim bulk tag edit: one track per line, tab separated.
+# Edit any column except the path, save and quit to review the changes.
+# Deleting a line leaves that track untouched.
+";
+
+/// Tabs and newlines would break the TSV layout, so they become spaces.
+fn clean(value: &str) -> String {
+	value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Dump the tags of `tracks` as a TSV document for editing.
+pub fn dump(tracks: &[Track]) -> String {
+	let mut out = String::from(HEADER_COMMENT);
+	let header: Vec<&str> = std::iter::once("path")
+		.chain(TagField::ALL.iter().map(|f| f.label()))
+		.collect();
+	out.push_str(&header.join("\t"));
+	out.push('\n');
+	for track in tracks {
+		let row: Vec<String> = std::iter::once(clean(&track.path))
+			.chain(TagField::ALL.iter().map(|f| clean(&f.get(track))))
+			.collect();
+		out.push_str(&row.join("\t"));
+		out.push('\n');
+	}
+	out
+}
+
+/// Diff an edited TSV document against the tracks it was dumped from.
+pub fn parse(contents: &str, originals: &[Track]) -> Result<Vec<BulkChange>, String> {
+	let by_path: HashMap<&str, &Track> = originals.iter().map(|t| (t.path.as_str(), t)).collect();
+	let mut changes = Vec::new();
+	let mut lines = contents
+		.lines()
+		.enumerate()
+		.filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+
+	// header names the columns, so reordering or dropping columns is fine
+	let Some((_, header)) = lines.next() else {
+		return Ok(changes);
+	};
+	let mut columns: Vec<Option<TagField>> = Vec::new(); // None is the path column
+	let mut path_col = None;
+	for (i, name) in header.split('\t').enumerate() {
+		let name = name.trim();
+		if name.eq_ignore_ascii_case("path") {
+			path_col = Some(i);
+			columns.push(None);
+		} else if let Some(field) = TagField::ALL
+			.iter()
+			.find(|f| f.label().eq_ignore_ascii_case(name))
+		{
+			columns.push(Some(*field));
+		} else {
+			return Err(format!("unknown column \"{}\"", name));
+		}
+	}
+	let Some(path_col) = path_col else {
+		return Err(String::from("the path column is missing"));
+	};
+
+	for (line_no, line) in lines {
+		let values: Vec<&str> = line.split('\t').collect();
+		if values.len() != columns.len() {
+			return Err(format!(
+				"line {}: expected {} columns, found {}",
+				line_no + 1,
+				columns.len(),
+				values.len()
+			));
+		}
+		let path = values[path_col];
+		let Some(track) = by_path.get(path) else {
+			return Err(format!("line {}: unknown path {}", line_no + 1, path));
+		};
+		for (column, value) in columns.iter().zip(values) {
+			let Some(field) = column else {
+				continue;
+			};
+			let old = clean(&field.get(track));
+			let new = value.trim().to_string();
+			if old.trim() == new {
+				continue;
+			}
+			tag_editor::validate(*field, &new).map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+			changes.push(BulkChange {
+				path: track.path.clone(),
+				field: *field,
+				old,
+				new,
+			});
+		}
+	}
+	Ok(changes)
+}
+
+/// Group changes by file, in the order the files first appear.
+pub fn group_by_path(changes: &[BulkChange]) -> Vec<(String, Vec<(TagField, String)>)> {
+	let mut grouped: Vec<(String, Vec<(TagField, String)>)> = Vec::new();
+	for change in changes {
+		match grouped.iter_mut().find(|(p, _)| p == &change.path) {
+			Some((_, fields)) => fields.push((change.field, change.new.clone())),
+			None => grouped.push((change.path.clone(), vec![(change.field, change.new.clone())])),
+		}
+	}
+	grouped
+}
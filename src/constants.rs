@@ -5,16 +5,33 @@ pub const APP_VER: &str = env!("CARGO_PKG_VERSION");
 pub const COPYRIGHT: &str = "© 2025 stim — MIT License";
 pub const WEBSITE: &str = "www.simondanielsson.se";
 
+// === library ===
+pub const DEFAULT_PATH_TEMPLATE: &str = "{artist}/{album}/{track} - {title}"; // used to fill in missing tags
+pub const DEFAULT_ORGANIZE_TEMPLATE: &str = "{albumartist}/{year} - {album}/{disc}{track:02} - {title}.{ext}"; // used by the organizer
+
 // === keymaps ===
 
 pub const K_QUIT: KeyCode = KeyCode::Char('q'); // quit
 pub const K_FIND: KeyCode = KeyCode::Char('f'); // find
 pub const K_CLEAR_FIND: KeyCode = KeyCode::Char('F'); // find
 pub const K_FAVORITE: KeyCode = KeyCode::Char('x'); // toggle favorite
+pub const K_RATE: [KeyCode; 6] = [
+	KeyCode::Char('0'),
+	KeyCode::Char('1'),
+	KeyCode::Char('2'),
+	KeyCode::Char('3'),
+	KeyCode::Char('4'),
+	KeyCode::Char('5'),
+]; // rate the selected album or track 1 to 5 stars, 0 to clear
 pub const K_CLEAR_FAV: KeyCode = KeyCode::Char('X'); // clear all favorites
+pub const K_UNDO: KeyCode = KeyCode::Char('u'); // undo the last change to the queue or favorites
+pub const K_REDO: KeyCode = KeyCode::Char('U'); // redo what was undone
 
 pub const K_SHUFFLE: KeyCode = KeyCode::Char('S'); // shuffle queue
+pub const K_AUTO_DJ: KeyCode = KeyCode::Char('R'); // what to add when the queue runs out: nothing, random, same artist/genre/era or favorites
 pub const K_SORT: KeyCode = KeyCode::Char('s'); // sort albums/tracks panel by A-Z or Z-A
+pub const K_SORT_KEY: KeyCode = KeyCode::Char('z'); // sort albums/tracks panel by name, year, rating, plays, skips or last played
+pub const K_FILTERS: KeyCode = KeyCode::Char('g'); // filter by genre, decade or release type
 pub const K_ADD_ALL_TRACKS: KeyCode = KeyCode::Char('t'); // add all tracks currently visible in the tracks panel to the queue at once
 
 pub const K_PLAY: KeyCode = KeyCode::Char(' '); // play/pause
@@ -30,7 +47,37 @@ pub const K_RIGHT: &[KeyCode] = &[KeyCode::Right, KeyCode::Char('i'), KeyCode::C
 pub const K_DOWN: &[KeyCode] = &[KeyCode::Down, KeyCode::Char('e'), KeyCode::Char('j')]; // move down
 pub const K_UP: &[KeyCode] = &[KeyCode::Up, KeyCode::Char('o'), KeyCode::Char('k')]; // move up
 
-pub const K_CLEAR: KeyCode = KeyCode::Esc; // clear queue
+pub const K_CLEAR: KeyCode = KeyCode::Esc; // clear the marks, or else the queue (the history is kept)
+pub const K_MARK: KeyCode = KeyCode::Char('v'); // mark/unmark the selected album, track or queue track
+pub const K_VISUAL: KeyCode = KeyCode::Char('V'); // start/stop marking every row the cursor moves over
 pub const K_MAIN: KeyCode = KeyCode::Char('a'); // main action
-pub const K_AUX: KeyCode = KeyCode::Char('A'); // aux action
+pub const K_AUX: KeyCode = KeyCode::Char('A'); // aux action (play next)
 pub const K_HL: KeyCode = KeyCode::Char('c'); // rotate highlight color
+pub const K_ISSUES: KeyCode = KeyCode::Char('!'); // show library scan issues
+pub const K_DUPLICATES: KeyCode = KeyCode::Char('D'); // show duplicate tracks
+pub const K_DETAILS: KeyCode = KeyCode::Char('I'); // show the tags and audio properties of the selected track
+pub const K_HISTORY: KeyCode = KeyCode::Char('H'); // show the listening history
+pub const K_STATS: KeyCode = KeyCode::Char('#'); // show library statistics
+pub const K_VERIFY: KeyCode = KeyCode::Char('v'); // verify the selected duplicates by their decoded audio
+pub const K_EDIT_TAGS: KeyCode = KeyCode::Char('E'); // edit the tags of the selected album/track
+pub const K_BULK_EDIT: KeyCode = KeyCode::Char('B'); // edit the tags of the selected album, search results or queue in $EDITOR
+pub const K_ORGANIZE: KeyCode = KeyCode::Char('O'); // move the selected files into folders named after their tags
+pub const K_COVER: KeyCode = KeyCode::Char('C'); // show/hide the album cover
+pub const K_LYRICS: KeyCode = KeyCode::Char('L'); // show/hide the lyrics pane
+pub const K_PLAY_STATS: KeyCode = KeyCode::Char('T'); // show/hide the plays, skips and last played columns in the tracks panel
+pub const K_LYRICS_UP: KeyCode = KeyCode::Char('('); // scroll unsynced lyrics up
+pub const K_LYRICS_DOWN: KeyCode = KeyCode::Char(')'); // scroll unsynced lyrics down
+pub const K_BROWSE_MODE: KeyCode = KeyCode::Char('b'); // switch between the flat, the artist -> album -> track and the folder and playlist browsers
+pub const K_OPEN: KeyCode = KeyCode::Enter; // open/close the selected folder, or replace the queue with the selected playlist
+pub const K_ADD_TO_PLAYLIST: KeyCode = KeyCode::Char('p'); // add the selected album/track to a playlist
+pub const K_NEW_PLAYLIST: KeyCode = KeyCode::Char('N'); // create a playlist
+pub const K_NEW_SMART_PLAYLIST: KeyCode = KeyCode::Char('P'); // create a playlist from find rules, e.g. "Jazz = genre:jazz is:favorite"
+pub const K_RENAME: KeyCode = KeyCode::Char('r'); // rename the selected playlist, or edit the rules of a smart playlist
+pub const K_DELETE: KeyCode = KeyCode::Char('d'); // delete the selected (smart) playlist, or remove the selected entry from it
+pub const K_MOVE_UP: KeyCode = KeyCode::Char('K'); // move the selected playlist entry or queue track up
+pub const K_MOVE_DOWN: KeyCode = KeyCode::Char('J'); // move the selected playlist entry or queue track down
+pub const K_MOVE_TO_END: KeyCode = KeyCode::Char('m'); // move the selected queue track to the end of the queue
+pub const K_EXPORT_QUEUE: KeyCode = KeyCode::Char('w'); // save the queue as an M3U, PLS or XSPF file
+pub const K_IMPORT_QUEUE: KeyCode = KeyCode::Char('W'); // add the tracks of an M3U, PLS or XSPF file to the queue
+pub const K_CONFIRM: KeyCode = KeyCode::Enter; // confirm the open popup
+pub const K_CLOSE_POPUP: KeyCode = KeyCode::Esc; // close the open popup
@@ -0,0 +1,118 @@
+use crate::load_album_and_track_lists::Track;
+use image::DynamicImage;
+use lofty::file::TaggedFileExt;
+use lofty::picture::PictureType;
+use ratatui_image::picker::{Picker, ProtocolType};
+use ratatui_image::protocol::StatefulProtocol;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Image files looked for next to the audio when a track has no embedded picture.
+const COVER_FILES: &[&str] = &["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// How the cover is drawn in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsProtocol {
+	#[default]
+	Auto,
+	Kitty,
+	Sixel,
+	HalfBlocks,
+}
+
+impl GraphicsProtocol {
+	/// Ask the terminal for its cell size and, for `Auto`, which graphics it
+	/// supports. Writes to and reads from stdio, so this has to run after
+	/// entering the alternate screen and before any events are read.
+	fn picker(self) -> Picker {
+		let mut picker = Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 16)));
+		match self {
+			GraphicsProtocol::Auto => {}
+			GraphicsProtocol::Kitty => picker.set_protocol_type(ProtocolType::Kitty),
+			GraphicsProtocol::Sixel => picker.set_protocol_type(ProtocolType::Sixel),
+			GraphicsProtocol::HalfBlocks => picker.set_protocol_type(ProtocolType::Halfblocks),
+		}
+		picker
+	}
+}
+
+/// Decoded covers per album, ready to be drawn at whatever size the panel has.
+pub struct CoverCache {
+	picker: Picker,
+	covers: HashMap<String, Option<StatefulProtocol>>,
+}
+
+/// Albums are identified the same way `create_album_list` groups them.
+pub fn album_key(track: &Track) -> String {
+	format!("{}\u{0}{}", track.album_artist, track.album)
+}
+
+impl CoverCache {
+	pub fn new(protocol: GraphicsProtocol) -> CoverCache {
+		CoverCache {
+			picker: protocol.picker(),
+			covers: HashMap::new(),
+		}
+	}
+
+	/// Decode the cover of `track`'s album unless that was already tried.
+	/// Called before drawing, so the draw itself never touches the disk.
+	pub fn load(&mut self, track: &Track) {
+		let picker = &self.picker;
+		self.covers
+			.entry(album_key(track))
+			.or_insert_with(|| load_cover(&track.path).map(|image| picker.new_resize_protocol(image)));
+	}
+
+	/// The cover of `track`'s album if it was loaded and the album has a usable picture.
+	pub fn get(&mut self, track: &Track) -> Option<&mut StatefulProtocol> {
+		self.covers.get_mut(&album_key(track))?.as_mut()
+	}
+
+	/// Forget everything, e.g. after tags or files changed.
+	pub fn clear(&mut self) {
+		self.covers.clear();
+	}
+}
+
+/// The embedded front cover of the file at `path`, or an image file in its folder.
+fn load_cover(path: &str) -> Option<DynamicImage> {
+	if let Ok(tagged_file) = lofty::read_from_path(path) {
+		let pictures: Vec<_> = tagged_file
+			.tags()
+			.iter()
+			.flat_map(|tag| tag.pictures())
+			.collect();
+		let front = pictures
+			.iter()
+			.find(|p| p.pic_type() == PictureType::CoverFront)
+			.or(pictures.first());
+		if let Some(image) = front.and_then(|p| image::load_from_memory(p.data()).ok()) {
+			return Some(image);
+		}
+	}
+
+	let dir = Path::new(path).parent()?;
+	let mut candidates: Vec<_> = fs::read_dir(dir)
+		.ok()?
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.path())
+		.filter(|p| {
+			let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+			let ext = p.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+			COVER_FILES.contains(&stem.as_str()) && COVER_EXTENSIONS.contains(&ext.as_str())
+		})
+		.collect();
+	// prefer the names in the order of COVER_FILES
+	candidates.sort_by_key(|p| {
+		let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+		COVER_FILES.iter().position(|name| *name == stem)
+	});
+	candidates
+		.iter()
+		.find_map(|p| image::load_from_memory(&fs::read(p).ok()?).ok())
+}
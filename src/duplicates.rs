@@ -0,0 +1,139 @@
+use crate::load_album_and_track_lists::Track;
+use rodio::{Decoder, Source};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::BufReader;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+const DURATION_TOLERANCE_SECS: u64 = 2; // copies may differ slightly in length
+const HASHED_SECONDS: u32 = 30; // how much decoded audio to compare
+
+/// Several copies of what appears to be the same recording.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+	pub artist: String,
+	pub title: String,
+	pub copies: Vec<Track>,
+	pub hashes: Option<Vec<Option<u64>>>, // decoded audio hash per copy, once verified
+}
+
+impl DuplicateGroup {
+	/// Whether copy `i` decodes to the same audio as the first copy.
+	pub fn copy_matches(&self, i: usize) -> Option<bool> {
+		let hashes = self.hashes.as_ref()?;
+		match (hashes.first()?, hashes.get(i)?) {
+			(Some(first), Some(hash)) => Some(first == hash),
+			_ => None,
+		}
+	}
+
+	pub fn paths(&self) -> Vec<String> {
+		self.copies.iter().map(|t| t.path.clone()).collect()
+	}
+}
+
+/// The copies of a group being hashed on another thread, as decoding takes a while.
+pub struct Verification {
+	pub paths: Vec<String>,
+	receiver: Receiver<Vec<Option<u64>>>,
+}
+
+impl Verification {
+	pub fn start(paths: Vec<String>) -> Self {
+		let (sender, receiver) = mpsc::channel();
+		let to_hash = paths.clone();
+		thread::spawn(move || {
+			let hashes = to_hash.iter().map(|path| audio_hash(path)).collect();
+			let _ = sender.send(hashes);
+		});
+		Self { paths, receiver }
+	}
+
+	/// The hash of each copy once they're all done, `None` for the ones that
+	/// couldn't be decoded.
+	pub fn finished(&self) -> Option<Vec<Option<u64>>> {
+		match self.receiver.try_recv() {
+			Ok(hashes) => Some(hashes),
+			Err(TryRecvError::Empty) => None,
+			Err(TryRecvError::Disconnected) => Some(vec![None; self.paths.len()]),
+		}
+	}
+}
+
+/// Lowercase, keep only letters and digits, and collapse whitespace so
+/// "The Song (feat. X)" and "the song  (Feat X)" compare equal.
+fn normalize(s: &str) -> String {
+	s.to_lowercase()
+		.chars()
+		.map(|c| if c.is_alphanumeric() { c } else { ' ' })
+		.collect::<String>()
+		.split_whitespace()
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Groups tracks by normalized artist and title, then splits each group
+/// into runs of tracks whose durations are within a couple of seconds.
+pub fn find_duplicates(tracks: &[Track]) -> Vec<DuplicateGroup> {
+	let mut by_name: HashMap<(String, String), Vec<&Track>> = HashMap::new();
+	for track in tracks {
+		let key = (normalize(&track.artist), normalize(&track.track_name));
+		by_name.entry(key).or_default().push(track);
+	}
+
+	let mut groups: Vec<DuplicateGroup> = Vec::new();
+	for (_, mut candidates) in by_name {
+		if candidates.len() < 2 {
+			continue;
+		}
+		candidates.sort_by_key(|t| t.length);
+		let mut run: Vec<Track> = Vec::new();
+		for track in candidates {
+			if let Some(last) = run.last()
+				&& track.length - last.length > DURATION_TOLERANCE_SECS
+			{
+				push_group(&mut groups, std::mem::take(&mut run));
+			}
+			run.push(track.clone());
+		}
+		push_group(&mut groups, run);
+	}
+
+	groups.sort_by(|a, b| {
+		(a.artist.to_lowercase(), a.title.to_lowercase())
+			.cmp(&(b.artist.to_lowercase(), b.title.to_lowercase()))
+	});
+	groups
+}
+
+fn push_group(groups: &mut Vec<DuplicateGroup>, copies: Vec<Track>) {
+	if copies.len() < 2 {
+		return;
+	}
+	groups.push(DuplicateGroup {
+		artist: copies[0].artist.clone(),
+		title: copies[0].track_name.clone(),
+		copies,
+		hashes: None,
+	});
+}
+
+/// Hash of the first seconds of decoded audio, so the same recording in
+/// different lossless containers (or the same file in two folders) matches.
+/// Only bit-identical audio matches: a lossy copy never matches its lossless
+/// source, nor do two different encodes.
+fn audio_hash(path: &str) -> Option<u64> {
+	let file = File::open(path).ok()?;
+	let source = Decoder::new(BufReader::new(file)).ok()?;
+	let mut hasher = DefaultHasher::new();
+	source.sample_rate().hash(&mut hasher);
+	source.channels().hash(&mut hasher);
+	let samples = (source.sample_rate() * source.channels() as u32 * HASHED_SECONDS) as usize;
+	for sample in source.take(samples) {
+		sample.to_bits().hash(&mut hasher);
+	}
+	Some(hasher.finish())
+}
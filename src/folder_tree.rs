@@ -0,0 +1,125 @@
+use crate::load_album_and_track_lists::{ScanIssue, Track};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// A folder of the library as found on disk.
+#[derive(Debug, Clone)]
+pub struct Folder {
+	pub path: PathBuf,
+	pub name: String,
+	pub depth: usize, // 0 for the library folder itself
+	pub has_subfolders: bool,
+	pub tracks: usize,   // playable files in this folder and below
+	pub problems: usize, // unreadable or unsupported files in this folder and below
+}
+
+/// The library's folders in filename order, built from the files the library
+/// scan walked over, so folders without any audio files are left out.
+#[derive(Debug, Clone, Default)]
+pub struct FolderTree {
+	pub folders: Vec<Folder>, // depth first, parents before their subfolders
+	expanded: HashSet<PathBuf>,
+}
+
+impl FolderTree {
+	/// Build the tree under `root`. Folders in `expanded` start out open; the
+	/// root always does.
+	pub fn build(root: &Path, tracks: &[Track], issues: &[ScanIssue], expanded: HashSet<PathBuf>) -> Self {
+		// PathBuf orders component by component, which puts every folder
+		// right before its contents, like a depth first walk sorted by name
+		let mut counts: BTreeMap<PathBuf, (usize, usize)> = BTreeMap::new();
+		counts.insert(root.to_path_buf(), (0, 0));
+		let files = tracks
+			.iter()
+			.map(|t| (Path::new(&t.path), true))
+			.chain(
+				issues
+					.iter()
+					.filter(|i| i.reason.is_skipped())
+					.map(|i| (Path::new(&i.path), false)),
+			);
+		for (file, playable) in files {
+			let Some(parent) = file.parent() else { continue };
+			if !parent.starts_with(root) {
+				continue;
+			}
+			for folder in parent.ancestors().take_while(|p| p.starts_with(root)) {
+				let entry = counts.entry(folder.to_path_buf()).or_default();
+				if playable {
+					entry.0 += 1;
+				} else {
+					entry.1 += 1;
+				}
+			}
+		}
+
+		let root_depth = root.components().count();
+		let paths: Vec<&PathBuf> = counts.keys().collect();
+		let folders = counts
+			.iter()
+			.enumerate()
+			.map(|(i, (path, (tracks, problems)))| Folder {
+				name: path
+					.file_name()
+					.unwrap_or(path.as_os_str())
+					.to_string_lossy()
+					.to_string(),
+				depth: path.components().count() - root_depth,
+				has_subfolders: paths.get(i + 1).is_some_and(|next| next.starts_with(path)),
+				path: path.clone(),
+				tracks: *tracks,
+				problems: *problems,
+			})
+			.collect();
+
+		let mut expanded = expanded;
+		expanded.insert(root.to_path_buf());
+		Self { folders, expanded }
+	}
+
+	/// Folders whose parents are all expanded, i.e. the rows of the pane.
+	pub fn visible(&self) -> Vec<&Folder> {
+		let mut rows = Vec::new();
+		let mut hidden_below: Option<usize> = None; // depth of a collapsed folder
+		for folder in &self.folders {
+			if let Some(depth) = hidden_below {
+				if folder.depth > depth {
+					continue;
+				}
+				hidden_below = None;
+			}
+			rows.push(folder);
+			if !self.is_expanded(&folder.path) {
+				hidden_below = Some(folder.depth);
+			}
+		}
+		rows
+	}
+
+	pub fn is_expanded(&self, path: &Path) -> bool {
+		self.expanded.contains(path)
+	}
+
+	/// Open or close the folder at `path`.
+	pub fn toggle(&mut self, path: &Path) {
+		if !self.expanded.remove(path) {
+			self.expanded.insert(path.to_path_buf());
+		}
+	}
+
+	/// The open folders, to carry over when the tree is rebuilt.
+	pub fn expanded(&self) -> HashSet<PathBuf> {
+		self.expanded.clone()
+	}
+}
+
+/// Every track in `folder` and its subfolders, in filename order.
+pub fn tracks_in(folder: &Path, tracks: &[Track]) -> Vec<Track> {
+	let mut found: Vec<Track> = tracks
+		.iter()
+		.filter(|t| Path::new(&t.path).starts_with(folder))
+		.cloned()
+		.collect();
+	found.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+	found
+}
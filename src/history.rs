@@ -0,0 +1,163 @@
+use crate::load_album_and_track_lists::Track;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One time a track was played, as a line of the history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Play {
+	pub path: String,
+	pub artist: String,
+	pub title: String,
+	pub album: String,
+	pub started: u64,  // seconds since the Unix epoch
+	pub listened: u64, // seconds, pauses not included
+	pub skipped: bool, // stopped before the end
+}
+
+impl Play {
+	pub fn new(track: &Track, started: u64, listened: u64, skipped: bool) -> Self {
+		Self {
+			path: track.path.clone(),
+			artist: track.artist.clone(),
+			title: track.track_name.clone(),
+			album: track.album.clone(),
+			started,
+			listened: listened.min(track.length),
+			skipped,
+		}
+	}
+}
+
+/// Everything that was played, oldest first. Kept as JSON lines next to the
+/// config file so a play only appends a line, and independent of the queue.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+	path: PathBuf,
+	pub plays: Vec<Play>,
+}
+
+impl History {
+	/// Read the history file; lines that can't be read are left out.
+	pub fn load(path: &Path) -> Self {
+		let plays = fs::read_to_string(path)
+			.map(|text| {
+				text.lines()
+					.filter_map(|line| serde_json::from_str(line).ok())
+					.collect()
+			})
+			.unwrap_or_default();
+		Self {
+			path: path.to_path_buf(),
+			plays,
+		}
+	}
+
+	pub fn record(&mut self, play: Play) {
+		if let Ok(line) = serde_json::to_string(&play)
+			&& let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&self.path)
+		{
+			let _ = writeln!(file, "{}", line);
+		}
+		self.plays.push(play);
+	}
+
+	/// What was played of each track, by `identity`, so the numbers stay
+	/// with a track when its file is moved or renamed.
+	pub fn play_stats(&self) -> HashMap<String, PlayStats> {
+		let mut stats: HashMap<String, PlayStats> = HashMap::new();
+		for play in &self.plays {
+			stats
+				.entry(identity(&play.artist, &play.album, &play.title))
+				.or_default()
+				.add(play.started, play.skipped);
+		}
+		stats
+	}
+}
+
+/// How often a track was played to the end or skipped, and when it was last started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayStats {
+	pub plays: u32,
+	pub skips: u32,
+	pub last_played: u64, // seconds since the Unix epoch, 0 if never
+}
+
+impl PlayStats {
+	pub fn of(track: &Track) -> Self {
+		Self {
+			plays: track.plays,
+			skips: track.skips,
+			last_played: track.last_played,
+		}
+	}
+
+	pub fn add(&mut self, started: u64, skipped: bool) {
+		if skipped {
+			self.skips += 1;
+		} else {
+			self.plays += 1;
+		}
+		self.last_played = self.last_played.max(started);
+	}
+
+	pub fn apply(&self, track: &mut Track) {
+		track.plays = self.plays;
+		track.skips = self.skips;
+		track.last_played = self.last_played;
+	}
+}
+
+/// What play statistics are kept by: artist, album and title, ignoring case.
+pub fn identity(artist: &str, album: &str, title: &str) -> String {
+	format!("{}\u{1f}{}\u{1f}{}", artist, album, title).to_lowercase()
+}
+
+pub fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
+
+/// "just now", "12m ago", "3h ago", "5d ago", or the date for older plays.
+pub fn format_ago(time: u64, now: u64) -> String {
+	let secs = now.saturating_sub(time);
+	match secs {
+		0..60 => String::from("just now"),
+		60..3600 => format!("{}m ago", secs / 60),
+		3600..86400 => format!("{}h ago", secs / 3600),
+		86400..2592000 => format!("{}d ago", secs / 86400),
+		_ => format_date(time),
+	}
+}
+
+/// The UTC date of `time` as YYYY-MM-DD.
+fn format_date(time: u64) -> String {
+	let (year, month, day) = civil_date(time);
+	format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The current year, in UTC.
+pub fn current_year() -> i64 {
+	civil_date(now()).0
+}
+
+/// Year, month and day of `time` in UTC.
+fn civil_date(time: u64) -> (i64, i64, i64) {
+	// days since 1970-01-01 to a civil date, after Howard Hinnant's civil_from_days
+	let days = (time / 86400) as i64 + 719468;
+	let era = days.div_euclid(146097);
+	let day_of_era = days.rem_euclid(146097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+	(year, month, day)
+}
@@ -1,16 +1,30 @@
+use crate::path_template;
+use crate::ratings;
+use lofty::error::ErrorKind;
 use lofty::file::AudioFile;
+use lofty::file::FileType;
 use lofty::file::TaggedFileExt;
-use lofty::prelude::ItemKey;
+use lofty::prelude::{Accessor, ItemKey};
 use lofty::probe::Probe;
-use lofty::read_from_path;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::Path;
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
-pub fn run() -> (Vec<Track>, Vec<Album>) {
-	let track_list: Vec<Track> = match create_song_list() {
+const VARIOUS_ARTISTS: &str = "Various Artists";
+// placeholders for tags that are missing and couldn't be taken from the path
+pub(crate) const UNKNOWN_ARTIST: &str = "Unknown Artist";
+pub(crate) const UNKNOWN_TITLE: &str = "Unknown Title";
+pub(crate) const UNKNOWN_ALBUM: &str = "Unknown Album";
+
+/// The tags `Track::missing_tags` checks for.
+pub const COMMON_TAGS: [&str; 6] = ["title", "artist", "album", "track", "year", "genre"];
+
+pub fn run(path_template: &str) -> (Vec<Track>, Vec<Album>, Vec<ScanIssue>) {
+	let mut issues: Vec<ScanIssue> = Vec::new();
+	let track_list: Vec<Track> = match create_song_list(path_template, &mut issues) {
 		Ok(vector) => vector,
 		Err(e) => panic!("Error occured when parsing songs: {}", e),
 	};
@@ -18,144 +32,670 @@ pub fn run() -> (Vec<Track>, Vec<Album>) {
 		Ok(vector) => vector,
 		Err(e) => panic!("Error occured when compiling the list of albums: {}", e),
 	};
-	(track_list, album_list)
+	issues.sort_by(|a, b| a.path.cmp(&b.path));
+	(track_list, album_list, issues)
 }
 
-fn create_album_list(tracks_vec: Vec<Track>) -> std::io::Result<Vec<Album>> {
+pub fn create_album_list(tracks_vec: Vec<Track>) -> std::io::Result<Vec<Album>> {
 	let mut album_map: HashMap<(String, String), Vec<Track>> = HashMap::new();
 
-	// group tracks by (artist, album)
+	// group tracks by (album artist, album) so compilations and albums
+	// with guest features stay together
 	for track in tracks_vec {
-		let key = (track.artist.clone(), track.album.clone());
-		album_map.entry(key).or_insert(Vec::new()).push(track);
+		let key = (track.album_artist.clone(), track.album.clone());
+		album_map.entry(key).or_default().push(track);
 	}
 
 	// convert into Vec<Album>
 	let mut album_list: Vec<Album> = Vec::new();
 	for ((artist, album_name), tracks) in album_map {
-		// sort tracks by track_num
+		// sort tracks by (disc, track, filename)
 		let mut sorted_tracks = tracks;
-		sorted_tracks.sort_by_key(|t| t.track_num);
+		sort_album_tracks(&mut sorted_tracks);
+		let compilation = sorted_tracks.iter().any(|t| t.compilation);
+		let inferred = sorted_tracks
+			.iter()
+			.any(|t| t.inferred.album_artist || t.inferred.album);
+		let year = sorted_tracks
+			.iter()
+			.map(|t| t.year)
+			.filter(|y| *y > 0)
+			.min()
+			.unwrap_or_default();
+		let mut genres: Vec<String> = Vec::new();
+		for genre in sorted_tracks.iter().flat_map(|t| &t.genres) {
+			if !genres.iter().any(|g| g.eq_ignore_ascii_case(genre)) {
+				genres.push(genre.clone());
+			}
+		}
+		let release_type = sorted_tracks
+			.iter()
+			.map(|t| t.release_type.clone())
+			.find(|t| !t.is_empty())
+			.unwrap_or_default();
 		album_list.push(Album {
 			artist,
 			name: album_name,
 			tracks: sorted_tracks,
+			compilation,
+			inferred,
 			favorite: false,
+			rating: 0,
+			year,
+			genres,
+			release_type,
 		});
 	}
 	Ok(album_list)
 }
 
+/// Tag names the release type is stored under, depending on the tagger.
+const RELEASE_TYPE_KEYS: &[&str] = &["RELEASETYPE", "MUSICBRAINZ_ALBUMTYPE", "MusicBrainz Album Type"];
+
+/// The year at the start of a date such as "1994-05-02".
+fn parse_year(date: &str) -> Option<i32> {
+	let digits: String = date.trim().chars().take(4).collect();
+	digits.parse().ok().filter(|y| *y > 0)
+}
+
+/// Multi-valued genres come as several tag items, or as one value separated
+/// by ';' or NUL (ID3v2.4).
+fn split_genres<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+	let mut genres: Vec<String> = Vec::new();
+	for genre in values.flat_map(|v| v.split([';', '\0'])) {
+		let genre = genre.trim();
+		if !genre.is_empty() && !genres.iter().any(|g| g.eq_ignore_ascii_case(genre)) {
+			genres.push(genre.to_string());
+		}
+	}
+	genres
+}
+
+/// Sort the tracks of an album by disc, then track number, then filename.
+pub fn sort_album_tracks(tracks: &mut [Track]) {
+	tracks.sort_by(|a, b| {
+		(a.disc_num, a.track_num, &a.path).cmp(&(b.disc_num, b.track_num, &b.path))
+	});
+}
+
+/// Returns a "Disc N" label if the track at `i` starts a new disc within a
+/// run of tracks from the same multi-disc album.
+pub fn disc_separator(tracks: &[Track], i: usize) -> Option<String> {
+	let track = &tracks[i];
+	if !track.is_multi_disc() {
+		return None;
+	}
+	let prev = i.checked_sub(1).map(|p| &tracks[p]);
+	let next = tracks.get(i + 1);
+	if let Some(prev) = prev
+		&& prev.same_album(track)
+		&& prev.disc_num == track.disc_num
+	{
+		return None;
+	}
+	let in_album_run = prev.is_some_and(|p| p.same_album(track))
+		|| next.is_some_and(|n| n.same_album(track));
+	if in_album_run {
+		Some(format!("Disc {}", track.disc_num))
+	} else {
+		None
+	}
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Album {
-	pub artist: String,
+	pub artist: String, // album artist
 	pub name: String,
 	pub tracks: Vec<Track>,
+	pub compilation: bool,
+	pub inferred: bool, // artist or name taken from the file path
 	pub favorite: bool,
+	pub rating: u8, // stars, 0 if unrated
+	pub year: i32,            // earliest year of its tracks, 0 if unknown
+	pub genres: Vec<String>,  // every genre of its tracks
+	pub release_type: String, // e.g. "album", "ep", "single; live"
+}
+
+impl Album {
+	/// Release type as shown in the Albums pane, e.g. "EP" or "Single, Live".
+	pub fn release_type_label(&self) -> String {
+		self.release_type
+			.split([';', '/'])
+			.map(|t| t.trim())
+			.filter(|t| !t.is_empty())
+			.map(|t| match t.to_lowercase().as_str() {
+				"ep" => String::from("EP"),
+				"lp" => String::from("LP"),
+				other => {
+					let mut chars = other.chars();
+					chars
+						.next()
+						.map(|c| c.to_uppercase().chain(chars).collect())
+						.unwrap_or_default()
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
+}
+
+/// Fields that were filled in from the file path rather than read from tags.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InferredFields {
+	pub artist: bool,
+	pub album_artist: bool,
+	pub album: bool,
+	pub title: bool,
+	pub track_num: bool,
+	pub disc_num: bool,
+}
+
+impl InferredFields {
+	pub fn any(&self) -> bool {
+		self.artist
+			|| self.album_artist
+			|| self.album
+			|| self.title
+			|| self.track_num
+			|| self.disc_num
+	}
+}
+
+/// Technical properties of the audio file.
+#[derive(Debug, Clone, Default)]
+pub struct AudioInfo {
+	pub format: String,   // codec and container, e.g. "FLAC", "MP3"
+	pub bitrate: u32,     // kbps
+	pub sample_rate: u32, // Hz, 0 if unknown
+	pub bit_depth: u8,    // 0 if unknown or not applicable (lossy formats)
+	pub channels: u8,     // 0 if unknown
+}
+
+/// Formats that decode to exactly what was recorded.
+const LOSSLESS_FORMATS: &[&str] = &["FLAC", "WAV", "AIFF", "APE", "WAVPACK", "ALAC"];
+
+impl AudioInfo {
+	pub fn is_lossless(&self) -> bool {
+		LOSSLESS_FORMATS.contains(&self.format.as_str())
+	}
+
+	/// Lossless with more than CD quality: over 16 bits or over 48 kHz.
+	pub fn is_hires(&self) -> bool {
+		self.is_lossless() && (self.bit_depth > 16 || self.sample_rate > 48000)
+	}
+
+	/// "mono", "stereo" or "6 ch".
+	pub fn channels_label(&self) -> String {
+		match self.channels {
+			0 => String::new(),
+			1 => String::from("mono"),
+			2 => String::from("stereo"),
+			n => format!("{} ch", n),
+		}
+	}
+
+	/// The known properties in one line, e.g. "FLAC · 24-bit · 96 kHz · stereo · 2304 kbps".
+	pub fn summary(&self) -> String {
+		let mut parts = vec![self.format.clone()];
+		if self.bit_depth > 0 {
+			parts.push(format!("{}-bit", self.bit_depth));
+		}
+		if self.sample_rate > 0 {
+			parts.push(sample_rate_label(self.sample_rate));
+		}
+		parts.push(self.channels_label());
+		if self.bitrate > 0 {
+			parts.push(format!("{} kbps", self.bitrate));
+		}
+		parts.retain(|p| !p.is_empty());
+		parts.join(" · ")
+	}
+}
+
+/// "44.1 kHz", "96 kHz", or "?" if the rate is unknown.
+pub fn sample_rate_label(hz: u32) -> String {
+	if hz == 0 {
+		String::from("?")
+	} else if hz.is_multiple_of(1000) {
+		format!("{} kHz", hz / 1000)
+	} else {
+		format!("{:.1} kHz", hz as f64 / 1000.0)
+	}
 }
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Track {
 	pub artist: String,
+	pub album_artist: String,
 	pub track_name: String,
 	pub track_num: i32,
+	pub track_total: i32,
+	pub disc_num: i32,
+	pub disc_total: i32,
 	pub path: String,
 	pub album: String,
+	pub year: i32, // 0 if unknown
+	pub genres: Vec<String>,
+	pub release_type: String, // RELEASETYPE / MusicBrainz album type, lowercase
 	pub length: u64,
+	pub audio: AudioInfo,
+	pub compilation: bool,
+	pub inferred: InferredFields,
 	pub favorite: bool,
+	pub rating: u8, // stars from the tags or the config, 0 if unrated
+	pub added: u64, // when the file was created or last modified, seconds since the Unix epoch
+	pub plays: u32,       // times played to the end, from the history
+	pub skips: u32,       // times stopped before the end
+	pub last_played: u64, // seconds since the Unix epoch, 0 if never
 }
 
 impl Track {
-	fn new(
-		artist: String,
-		track_name: String,
-		track_num: i32,
-		path: String,
-		album: String,
-		length: u64,
-	) -> Self {
+	pub(crate) fn new(path: String) -> Self {
 		Self {
-			artist,
-			track_name,
-			track_num,
+			artist: String::from(UNKNOWN_ARTIST),
+			album_artist: String::new(),
+			track_name: String::from(UNKNOWN_TITLE),
+			track_num: 0,
+			track_total: 0,
+			disc_num: 0,
+			disc_total: 0,
 			path,
-			album,
-			length,
+			album: String::from(UNKNOWN_ALBUM),
+			year: 0,
+			genres: Vec::new(),
+			release_type: String::new(),
+			length: 0,
+			audio: AudioInfo::default(),
+			compilation: false,
+			inferred: InferredFields::default(),
 			favorite: false,
+			rating: 0,
+			added: 0,
+			plays: 0,
+			skips: 0,
+			last_played: 0,
+		}
+	}
+
+	/// Names of the common tags this track doesn't have, counting values
+	/// taken from the file path as missing.
+	pub fn missing_tags(&self) -> Vec<&'static str> {
+		let missing = [
+			self.inferred.title || self.track_name == UNKNOWN_TITLE,
+			self.inferred.artist || self.artist == UNKNOWN_ARTIST,
+			self.inferred.album || self.album == UNKNOWN_ALBUM,
+			self.inferred.track_num || self.track_num == 0,
+			self.year == 0,
+			self.genres.is_empty(),
+		];
+		COMMON_TAGS
+			.into_iter()
+			.zip(missing)
+			.filter(|(_, missing)| *missing)
+			.map(|(name, _)| name)
+			.collect()
+	}
+
+	/// What the album artist falls back to without a tag: "Various Artists"
+	/// on compilations, the track artist otherwise.
+	pub fn default_album_artist(&self) -> String {
+		if self.compilation {
+			String::from(VARIOUS_ARTISTS)
+		} else {
+			self.artist.clone()
+		}
+	}
+
+	pub fn is_multi_disc(&self) -> bool {
+		self.disc_total > 1 || self.disc_num > 1
+	}
+
+	/// Genres as one string, e.g. "Jazz; Funk".
+	pub fn genre_label(&self) -> String {
+		self.genres.join("; ")
+	}
+
+	pub fn same_album(&self, other: &Track) -> bool {
+		self.album_artist == other.album_artist && self.album == other.album
+	}
+
+	/// Track number as shown in listings, e.g. "3" or "2-03" on multi-disc albums.
+	pub fn number_label(&self) -> String {
+		if self.is_multi_disc() {
+			format!("{}-{:02}", self.disc_num, self.track_num)
+		} else {
+			self.track_num.to_string()
 		}
 	}
 }
 
-fn create_song_list() -> std::io::Result<Vec<Track>> {
-	let stim_library_dir_path: String = match env::var("HOME") {
+fn format_name(file_type: FileType) -> String {
+	match file_type {
+		FileType::Flac => String::from("FLAC"),
+		FileType::Mpeg => String::from("MP3"),
+		FileType::Wav => String::from("WAV"),
+		other => format!("{:?}", other).to_uppercase(),
+	}
+}
+
+/// Parses "n" and "n/total" forms, returning (n, total).
+fn parse_number_pair(value: &str) -> (i32, Option<i32>) {
+	let mut parts = value.splitn(2, '/');
+	let num = parts
+		.next()
+		.and_then(|n| n.trim().parse::<i32>().ok())
+		.unwrap_or_default();
+	let total = parts.next().and_then(|t| t.trim().parse::<i32>().ok());
+	(num, total)
+}
+
+/// A file the library scan skipped or could only partially read.
+#[derive(Debug, Clone)]
+pub struct ScanIssue {
+	pub path: String,
+	pub reason: IssueReason,
+}
+
+#[derive(Debug, Clone)]
+pub enum IssueReason {
+	Unreadable(String),
+	UnsupportedCodec(String),
+	MissingTags(String), // comma separated field names
+	ZeroDuration,
+	NonUtf8Path,
+}
+
+impl IssueReason {
+	/// Whether the file was left out of the library entirely.
+	pub fn is_skipped(&self) -> bool {
+		matches!(
+			self,
+			IssueReason::Unreadable(_)
+				| IssueReason::UnsupportedCodec(_)
+				| IssueReason::NonUtf8Path
+		)
+	}
+}
+
+impl std::fmt::Display for IssueReason {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			IssueReason::Unreadable(e) => write!(f, "unreadable: {}", e),
+			IssueReason::UnsupportedCodec(ext) => write!(f, "unsupported format: {}", ext),
+			IssueReason::MissingTags(fields) => write!(f, "missing tags: {}", fields),
+			IssueReason::ZeroDuration => write!(f, "zero duration"),
+			IssueReason::NonUtf8Path => write!(f, "path is not valid UTF-8"),
+		}
+	}
+}
+
+/// The "stim-library" folder in the home directory.
+pub fn library_dir() -> String {
+	match env::var("HOME") {
 		Ok(home) => format!("{}/stim-library/", home),
 		Err(e) => panic!("Home directory could not be found: {}", e),
-	};
+	}
+}
+
+pub const SUPPORTED_EXTENSIONS: [&str; 3] = ["wav", "mp3", "flac"];
+// audio files we recognise but can't play, reported instead of silently ignored
+const UNSUPPORTED_EXTENSIONS: [&str; 12] = [
+	"ogg", "oga", "opus", "m4a", "aac", "alac", "wma", "aiff", "aif", "ape", "wv", "mpc",
+];
+
+fn create_song_list(
+	path_template: &str,
+	issues: &mut Vec<ScanIssue>,
+) -> std::io::Result<Vec<Track>> {
+	let stim_library_dir_path: String = library_dir();
 	// create stim library in home directory if it doesn't exist
 	fs::create_dir_all(&stim_library_dir_path)?;
 	let mut tracks_vec: Vec<Track> = Vec::new();
-	let extensions_to_search_for = ["wav", "mp3", "flac"];
-	for entry in WalkDir::new(&stim_library_dir_path)
-		.into_iter()
-		.filter_map(Result::ok)
-		.filter(|e| e.file_type().is_file())
-	{
-		if let Some(ext) = entry.path().extension().and_then(|s| s.to_str()) {
-			if extensions_to_search_for.contains(&ext) {
-				let track: Track =
-					match get_track_metadata(entry.path().to_str().unwrap()) {
-						Ok(metadata) => metadata,
-						Err(_) => continue,
-					};
-				tracks_vec.push(track);
+	for entry in WalkDir::new(&stim_library_dir_path).into_iter() {
+		let entry = match entry {
+			Ok(entry) => entry,
+			Err(e) => {
+				issues.push(ScanIssue {
+					path: e
+						.path()
+						.map(|p| p.to_string_lossy().to_string())
+						.unwrap_or_default(),
+					reason: IssueReason::Unreadable(e.to_string()),
+				});
+				continue;
 			}
+		};
+		if !entry.file_type().is_file() {
+			continue;
 		}
+		let Some(ext) = entry
+			.path()
+			.extension()
+			.map(|s| s.to_string_lossy().to_lowercase())
+		else {
+			continue;
+		};
+		if UNSUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+			issues.push(ScanIssue {
+				path: entry.path().to_string_lossy().to_string(),
+				reason: IssueReason::UnsupportedCodec(ext),
+			});
+			continue;
+		}
+		if !SUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+			continue;
+		}
+		let Some(file_path) = entry.path().to_str() else {
+			issues.push(ScanIssue {
+				path: entry.path().to_string_lossy().to_string(),
+				reason: IssueReason::NonUtf8Path,
+			});
+			continue;
+		};
+		let rel_path = entry
+			.path()
+			.strip_prefix(&stim_library_dir_path)
+			.unwrap_or(entry.path());
+		let mut warnings = Vec::new();
+		match get_track_metadata(file_path, rel_path, path_template, &mut warnings) {
+			Ok(track) => tracks_vec.push(track),
+			Err(reason) => warnings.push(reason),
+		}
+		issues.extend(warnings.into_iter().map(|reason| ScanIssue {
+			path: file_path.to_string(),
+			reason,
+		}));
 	}
 	Ok(tracks_vec)
 }
 
-fn get_track_metadata(file_path: &str) -> std::io::Result<Track> {
+/// Reads a track's tags and properties. Problems that don't stop the track
+/// from being added (missing tags, zero duration) are pushed to `warnings`.
+fn get_track_metadata(
+	file_path: &str,
+	rel_path: &Path,
+	path_template: &str,
+	warnings: &mut Vec<IssueReason>,
+) -> Result<Track, IssueReason> {
 	let path = Path::new(file_path);
-	let tagged_file = Probe::open(&path).unwrap().read();
+	let probe = Probe::open(path)
+		.map_err(|e| IssueReason::Unreadable(e.to_string()))?
+		.guess_file_type()
+		.map_err(|e| IssueReason::Unreadable(e.to_string()))?;
+	let (tagged_file, popm_rating) = ratings::read_probed(probe)
+		.map_err(|e| match e.kind() {
+			ErrorKind::UnknownFormat => IssueReason::UnsupportedCodec(
+				path.extension()
+					.map(|s| s.to_string_lossy().to_string())
+					.unwrap_or_default(),
+			),
+			_ => IssueReason::Unreadable(e.to_string()),
+		})?;
 
-	let mut artist = String::from("Unknown Artist");
-	let mut title = String::from("Unknown Title");
-	let mut album = String::from("Unknown Album");
-	let mut track_num: i32 = 0;
-	let mut length: u64 = 0;
+	let mut track = Track::new(file_path.to_string());
+	track.added = fs::metadata(path)
+		.and_then(|m| m.created().or_else(|_| m.modified()))
+		.ok()
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_secs())
+		.unwrap_or_default();
+	let mut album_artist: Option<String> = None;
+	let mut tagged = InferredFields::default(); // fields found in tags
 
-	// read_from_path returns Result<TaggedFile, Error>
-	if let Ok(tagged_file) = read_from_path(file_path) {
-		let duration_secs: u64 = tagged_file.properties().duration().as_secs();
-		length = duration_secs;
+	let properties = tagged_file.properties();
+	let duration_secs: u64 = properties.duration().as_secs();
+	track.length = duration_secs;
+	track.audio = AudioInfo {
+		format: format_name(tagged_file.file_type()),
+		bitrate: properties
+			.audio_bitrate()
+			.or(properties.overall_bitrate())
+			.unwrap_or_default(),
+		sample_rate: properties.sample_rate().unwrap_or_default(),
+		bit_depth: properties.bit_depth().unwrap_or_default(),
+		channels: properties.channels().unwrap_or_default(),
+	};
+	if duration_secs == 0 {
+		warnings.push(IssueReason::ZeroDuration);
 	}
 
-	if let Some(tag) = tagged_file.unwrap().primary_tag() {
+	if let Some(tag) = tagged_file.primary_tag() {
 		if let Some(t) = tag.get_string(&ItemKey::TrackTitle) {
-			title = t.to_string();
+			track.track_name = t.to_string();
+			tagged.title = true;
 		}
 		if let Some(n) = tag.get_string(&ItemKey::TrackNumber) {
-			track_num = match n.parse::<i32>() {
-				Ok(n) => n,
-				Err(_) => 0,
-			}
+			let (num, total) = parse_number_pair(n);
+			track.track_num = num;
+			track.track_total = total.unwrap_or_default();
+			tagged.track_num = num > 0;
+		}
+		if let Some(t) = tag.get_string(&ItemKey::TrackTotal)
+			&& let (total, _) = parse_number_pair(t)
+			&& total > 0
+		{
+			track.track_total = total;
+		}
+		if let Some(n) = tag.get_string(&ItemKey::DiscNumber) {
+			let (num, total) = parse_number_pair(n);
+			track.disc_num = num;
+			track.disc_total = total.unwrap_or_default();
+			tagged.disc_num = num > 0;
+		}
+		if let Some(t) = tag.get_string(&ItemKey::DiscTotal)
+			&& let (total, _) = parse_number_pair(t)
+			&& total > 0
+		{
+			track.disc_total = total;
 		}
 		if let Some(a) = tag.get_string(&ItemKey::TrackArtist) {
-			artist = a.to_string();
+			track.artist = a.to_string();
+			tagged.artist = true;
+		}
+		if let Some(aa) = tag.get_string(&ItemKey::AlbumArtist)
+			&& !aa.trim().is_empty()
+		{
+			album_artist = Some(aa.to_string());
 		}
 		if let Some(al) = tag.get_string(&ItemKey::AlbumTitle) {
-			album = al.to_string();
+			track.album = al.to_string();
+			tagged.album = true;
+		}
+		// YEAR, then DATE, then the original/release dates
+		track.year = tag
+			.year()
+			.map(|y| y as i32)
+			.or_else(|| {
+				[ItemKey::OriginalReleaseDate, ItemKey::ReleaseDate]
+					.iter()
+					.filter_map(|key| tag.get_string(key))
+					.find_map(parse_year)
+			})
+			.unwrap_or_default();
+		track.genres = split_genres(tag.get_strings(&ItemKey::Genre));
+		track.release_type = tag
+			.items()
+			.filter(|item| match item.key() {
+				ItemKey::Unknown(key) => RELEASE_TYPE_KEYS
+					.iter()
+					.any(|k| k.eq_ignore_ascii_case(key)),
+				_ => false,
+			})
+			.filter_map(|item| item.value().text())
+			.map(|t| t.trim().to_lowercase())
+			.find(|t| !t.is_empty())
+			.unwrap_or_default();
+		if let Some(c) = tag.get_string(&ItemKey::FlagCompilation) {
+			track.compilation = matches!(c.trim(), "1" | "true" | "True" | "TRUE");
+		}
+		track.rating = ratings::read(tag);
+		if track.rating == 0 {
+			track.rating = popm_rating;
+		}
+	}
+
+	let missing: Vec<&str> = [
+		("title", tagged.title),
+		("artist", tagged.artist),
+		("album", tagged.album),
+	]
+	.iter()
+	.filter(|(_, found)| !found)
+	.map(|(name, _)| *name)
+	.collect();
+	if !missing.is_empty() {
+		warnings.push(IssueReason::MissingTags(missing.join(", ")));
+	}
+
+	// fill in whatever the tags didn't provide from the file path
+	let fields = path_template::infer_fields(path_template, rel_path);
+	if !tagged.artist
+		&& let Some(a) = fields.get("artist")
+	{
+		track.artist = a.clone();
+		track.inferred.artist = true;
+	}
+	if !tagged.album
+		&& let Some(al) = fields.get("album")
+	{
+		track.album = al.clone();
+		track.inferred.album = true;
+	}
+	if !tagged.title {
+		if let Some(t) = fields.get("title") {
+			track.track_name = t.clone();
+			track.inferred.title = true;
+		} else if let Some(stem) = rel_path.file_stem().and_then(|s| s.to_str()) {
+			track.track_name = stem.to_string();
+			track.inferred.title = true;
 		}
 	}
+	if !tagged.track_num
+		&& let Some(n) = fields.get("track")
+	{
+		track.track_num = parse_number_pair(n).0;
+		track.inferred.track_num = true;
+	}
+	if !tagged.disc_num
+		&& let Some(n) = fields.get("disc")
+	{
+		track.disc_num = parse_number_pair(n).0;
+		track.inferred.disc_num = true;
+	}
+	if album_artist.is_none()
+		&& let Some(aa) = fields.get("albumartist")
+	{
+		album_artist = Some(aa.clone());
+		track.inferred.album_artist = true;
+	}
+
+	// fall back to the track artist, or "Various Artists" for compilations
+	if album_artist.is_none() && !track.compilation {
+		track.inferred.album_artist = track.inferred.artist;
+	}
+	track.album_artist = album_artist.unwrap_or_else(|| track.default_album_artist());
 
-	Ok(Track::new(
-		artist,
-		title,
-		track_num,
-		file_path.to_string(),
-		album,
-		length,
-	))
+	Ok(track)
 }
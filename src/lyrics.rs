@@ -0,0 +1,225 @@
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::id3::v2::{Frame, Id3v2Tag, SynchronizedTextFrame, TimestampFormat};
+use lofty::iff::wav::WavFile;
+use lofty::mpeg::MpegFile;
+use lofty::prelude::ItemKey;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct LyricLine {
+	pub time: Option<Duration>, // None for unsynced lyrics
+	pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lyrics {
+	pub lines: Vec<LyricLine>,
+	pub synced: bool,
+	pub source: &'static str, // where they were found, shown in the pane title
+}
+
+impl Lyrics {
+	/// Index of the line being sung at `position`, for synced lyrics.
+	pub fn current_line(&self, position: Duration) -> Option<usize> {
+		if !self.synced {
+			return None;
+		}
+		self.lines
+			.iter()
+			.rposition(|line| line.time.is_some_and(|t| t <= position))
+	}
+
+	/// Rows taken up by the lines before `index` when wrapped at `width`.
+	pub fn rows_before(&self, index: usize, width: usize) -> usize {
+		self.lines
+			.iter()
+			.take(index)
+			.map(|line| line.text.chars().count().div_ceil(width.max(1)).max(1))
+			.sum()
+	}
+}
+
+/// Lyrics for the file at `path`: a `.lrc` next to it, then SYLT, then
+/// USLT/LYRICS tags. Synced lyrics win over unsynced ones.
+pub fn load(path: &str) -> Option<Lyrics> {
+	let file = Path::new(path);
+	for ext in ["lrc", "LRC"] {
+		if let Ok(bytes) = fs::read(file.with_extension(ext)) {
+			let text = String::from_utf8_lossy(&bytes);
+			if let Some(lyrics) = parse_lrc(text.trim_start_matches('\u{feff}'), ".lrc") {
+				return Some(lyrics);
+			}
+		}
+	}
+
+	if let Some(lyrics) = read_sylt(file) {
+		return Some(lyrics);
+	}
+
+	// USLT, the LYRICS Vorbis comment and the like all map to ItemKey::Lyrics
+	let tagged_file = lofty::read_from_path(file).ok()?;
+	tagged_file
+		.tags()
+		.iter()
+		.filter_map(|tag| tag.get_string(&ItemKey::Lyrics))
+		.find_map(|text| parse_lrc(text, "tags"))
+}
+
+/// Parse LRC text. Lines without timestamps make the result unsynced.
+pub fn parse_lrc(text: &str, source: &'static str) -> Option<Lyrics> {
+	let mut offset_ms: i64 = 0;
+	let mut synced: Vec<LyricLine> = Vec::new();
+	let mut plain: Vec<LyricLine> = Vec::new();
+
+	for raw in text.lines() {
+		let mut rest = raw.trim();
+		let mut times = Vec::new();
+		let mut is_tag = false;
+		while let Some(stripped) = rest.strip_prefix('[') {
+			let Some(end) = stripped.find(']') else { break };
+			let inside = &stripped[..end];
+			if let Some(time) = parse_timestamp(inside) {
+				times.push(time);
+			} else if let Some(value) = inside.strip_prefix("offset:") {
+				offset_ms = value.trim().parse().unwrap_or(0);
+				is_tag = true;
+			} else if inside.contains(':') {
+				is_tag = true; // [ar:...], [ti:...] and other id tags
+			} else {
+				break;
+			}
+			rest = stripped[end + 1..].trim_start();
+		}
+		let text = strip_word_timings(rest);
+		if times.is_empty() {
+			if !is_tag {
+				plain.push(LyricLine { time: None, text });
+			}
+		} else {
+			for time in times {
+				synced.push(LyricLine {
+					time: Some(time),
+					text: text.clone(),
+				});
+			}
+		}
+	}
+
+	if !synced.is_empty() {
+		for line in synced.iter_mut() {
+			// a positive offset makes the lyrics come sooner
+			let ms = line.time.unwrap_or_default().as_millis() as i64 - offset_ms;
+			line.time = Some(Duration::from_millis(ms.max(0) as u64));
+		}
+		synced.sort_by_key(|line| line.time);
+		return Some(Lyrics {
+			lines: synced,
+			synced: true,
+			source,
+		});
+	}
+
+	// drop the blank lines around the text
+	while plain.last().is_some_and(|l| l.text.is_empty()) {
+		plain.pop();
+	}
+	let first = plain.iter().position(|l| !l.text.is_empty())?;
+	Some(Lyrics {
+		lines: plain.split_off(first),
+		synced: false,
+		source,
+	})
+}
+
+/// "mm:ss", "mm:ss.xx" or "mm:ss:xx".
+fn parse_timestamp(s: &str) -> Option<Duration> {
+	let (minutes, seconds) = s.split_once(':')?;
+	let minutes: u64 = minutes.trim().parse().ok()?;
+	let (seconds, fraction) = match seconds.split_once(['.', ':']) {
+		Some((s, f)) => (s, f),
+		None => (seconds, ""),
+	};
+	let seconds: u64 = seconds.trim().parse().ok()?;
+	let millis = if fraction.is_empty() {
+		0
+	} else {
+		let digits: String = fraction.chars().take(3).collect();
+		let value: u64 = digits.parse().ok()?;
+		value * 10u64.pow(3 - digits.len() as u32)
+	};
+	Some(Duration::from_millis((minutes * 60 + seconds) * 1000 + millis))
+}
+
+/// Remove enhanced LRC word timings such as "<00:12.34>".
+fn strip_word_timings(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('<') {
+		match rest[start..].find('>') {
+			Some(end) if parse_timestamp(&rest[start + 1..start + end]).is_some() => {
+				out.push_str(&rest[..start]);
+				rest = &rest[start + end + 1..];
+			}
+			_ => {
+				out.push_str(&rest[..=start]);
+				rest = &rest[start + 1..];
+			}
+		}
+	}
+	out.push_str(rest);
+	out.trim().to_string()
+}
+
+/// SYLT frames are only kept in the raw ID3v2 tag, so read it directly.
+fn read_sylt(path: &Path) -> Option<Lyrics> {
+	let ext = path.extension()?.to_str()?.to_lowercase();
+	let mut file = fs::File::open(path).ok()?;
+	let options = ParseOptions::new();
+	let (tag, sample_rate): (Id3v2Tag, u32) = match ext.as_str() {
+		"mp3" => {
+			let mpeg = MpegFile::read_from(&mut file, options).ok()?;
+			let rate = mpeg.properties().sample_rate();
+			(mpeg.id3v2()?.clone(), rate)
+		}
+		"wav" => {
+			let wav = WavFile::read_from(&mut file, options).ok()?;
+			let rate = wav.properties().sample_rate();
+			(wav.id3v2()?.clone(), rate)
+		}
+		_ => return None,
+	};
+
+	tag.into_iter().find_map(|frame| {
+		if frame.id_str() != "SYLT" {
+			return None;
+		}
+		let flags = frame.flags();
+		let Frame::Binary(binary) = frame else {
+			return None;
+		};
+		let sylt = SynchronizedTextFrame::parse(&binary.data, flags).ok()?;
+		let lines: Vec<LyricLine> = sylt
+			.content
+			.iter()
+			.map(|(stamp, text)| {
+				let ms = match sylt.timestamp_format {
+					TimestampFormat::MS => *stamp as u64,
+					// MPEG frames of 1152 samples each
+					TimestampFormat::MPEG => *stamp as u64 * 1152 * 1000 / sample_rate.max(1) as u64,
+				};
+				LyricLine {
+					time: Some(Duration::from_millis(ms)),
+					text: text.trim_matches(['\n', '\r']).to_string(),
+				}
+			})
+			.collect();
+		(!lines.is_empty()).then_some(Lyrics {
+			lines,
+			synced: true,
+			source: "SYLT",
+		})
+	})
+}
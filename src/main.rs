@@ -6,18 +6,37 @@ use crossterm::{
 use ratatui::{
 	Terminal,
 	backend::CrosstermBackend,
-	layout::{Alignment, Constraint, Layout, Position},
+	layout::{Alignment, Constraint, Layout, Position, Rect},
 	style::{Color, Modifier, Style},
-	text::Text,
-	widgets::{Block, BorderType, Borders, Cell, List, ListItem, Paragraph, Row, Table},
+	text::{Line, Text},
+	widgets::{Block, BorderType, Borders, Cell, List, ListItem, Paragraph, Row, Table, Wrap},
 };
+use ratatui_image::{Resize, StatefulImage};
 
 pub mod app;
+pub mod auto_dj;
+pub mod bulk_edit;
 pub mod constants;
+pub mod cover_art;
+pub mod duplicates;
+pub mod folder_tree;
+pub mod history;
 pub mod load_album_and_track_lists;
+pub mod lyrics;
+pub mod organizer;
+pub mod path_template;
 pub mod player;
+pub mod playlists;
+pub mod popups;
+pub mod prompt;
+pub mod query;
+pub mod ratings;
+pub mod smart_playlists;
+pub mod stats;
+pub mod tag_editor;
 
 use crate::app::*;
+use crate::auto_dj::AutoDj;
 use crate::constants::*;
 
 fn main() -> std::io::Result<()> {
@@ -27,28 +46,41 @@ fn main() -> std::io::Result<()> {
 	let backend = CrosstermBackend::new(stdout);
 	let mut terminal = Terminal::new(backend)?;
 
-	let (track_list, album_list) = load_album_and_track_lists::run();
-
 	let mut config_path = dirs::config_dir().unwrap_or_else(|| ".".into());
 	config_path.push("my_app");
 	std::fs::create_dir_all(&config_path).ok();
 	config_path.push("app_config.json");
 
-	let app_config = AppConfig::load(&config_path);
+	let mut app_config = AppConfig::load(&config_path);
+
+	let (track_list, album_list, scan_issues) =
+		load_album_and_track_lists::run(app_config.path_template());
+	if app_config.migrate_album_keys(&album_list) {
+		app_config.save(&config_path);
+	}
 
 	// init app state
 	let mut app = App::new(
 		album_list,
 		track_list,
+		scan_issues,
 		app_config.get_color(),
 		&app_config,
 		&config_path,
 	);
 	app.sort_lists();
 	app.apply_favorites(&app_config);
+	app.apply_play_stats();
+	app.apply_ratings();
 
 	// app
 	loop {
+		app.poll_verifications();
+		if app.config.show_cover()
+			&& let Some(track) = app.cover_track()
+		{
+			app.covers.load(&track);
+		}
 		terminal.draw(|f| {
 			let hl_color = app.highlight_color;
 			let size = f.area();
@@ -57,129 +89,540 @@ fn main() -> std::io::Result<()> {
 				Constraint::Fill(1),   // main
 			])
 			.split(size);
-			let horizontal_chunks = Layout::horizontal([
-				Constraint::Percentage(40), // albums
-				Constraint::Percentage(40), // tracks
-				Constraint::Fill(1),        // queue
-			])
-			.split(vertical_chunks[1]);
+			let show_lyrics = app.config.show_lyrics();
+			let mode = app.config.browse_mode();
+			// (artists/folders/playlists, albums, tracks/playlist entries) widths in percent
+			let (left_pct, albums_pct, tracks_pct) = match (mode, show_lyrics) {
+				(BrowseMode::Flat, false) => (0, 40, 40),
+				(BrowseMode::Flat, true) => (0, 30, 30),
+				(BrowseMode::Cascade, false) => (20, 30, 30),
+				(BrowseMode::Cascade, true) => (15, 20, 25),
+				(BrowseMode::Folders, false) => (35, 0, 45),
+				(BrowseMode::Folders, true) => (25, 0, 35),
+				(BrowseMode::Playlists, false) => (25, 0, 55),
+				(BrowseMode::Playlists, true) => (20, 0, 40),
+			};
+			let mut constraints = Vec::new();
+			if left_pct > 0 {
+				constraints.push(Constraint::Percentage(left_pct)); // artists, folders or playlists
+			}
+			if albums_pct > 0 {
+				constraints.push(Constraint::Percentage(albums_pct)); // albums
+			}
+			constraints.push(Constraint::Percentage(tracks_pct)); // tracks or playlist entries
+			if show_lyrics {
+				constraints.push(Constraint::Fill(1)); // lyrics
+			}
+			constraints.push(Constraint::Fill(1)); // queue
+			let horizontal_chunks = Layout::horizontal(constraints).split(vertical_chunks[1]);
+			let first = usize::from(left_pct > 0);
+			let albums_chunk = horizontal_chunks[first];
+			let tracks_chunk = horizontal_chunks[first + usize::from(albums_pct > 0)];
+			let lyrics_chunk = horizontal_chunks[horizontal_chunks.len() - 2];
+			let queue_column = horizontal_chunks[horizontal_chunks.len() - 1];
+			// the cover replaces the logo, roughly square since cells are about twice as tall as wide
+			let cover_rows = (queue_column.width.saturating_sub(2) / 2 + 2)
+				.min(queue_column.height / 2);
 			let queue_logo_chunk = Layout::vertical([
 				Constraint::Max(3),
 				Constraint::Fill(1),
-				Constraint::Max(8),
+				if app.config.show_cover() {
+					Constraint::Length(cover_rows)
+				} else {
+					Constraint::Max(8)
+				},
 			])
-			.split(horizontal_chunks[2]);
+			.split(queue_column);
 
 			let highlight_style = Style::default()
 				.fg(Color::Black)
 				.add_modifier(Modifier::BOLD)
 				.bg(hl_color);
+			// fields filled in from the file path instead of tags
+			let inferred_style = Style::default()
+				.fg(Color::DarkGray)
+				.add_modifier(Modifier::ITALIC);
+			// rows marked for an action on several rows at once
+			let marked_style = Style::default().fg(hl_color).add_modifier(Modifier::BOLD);
+			let visual = app.marks.is_visual();
+			let marked_title = |title: &str, marked: usize| match marked {
+				0 => title.to_string(),
+				_ if visual => format!("{} ({} marked, visual)", title, marked),
+				_ => format!("{} ({} marked)", title, marked),
+			};
 
-			// albums
-			let album_has_focus = matches!(app.active_panel, ActivePanel::Albums);
+			// artists
+			if mode == BrowseMode::Cascade {
+				let artists_has_focus = matches!(app.active_panel, ActivePanel::Artists);
+				let artist_rows: Vec<Row> = app
+					.artists
+					.iter()
+					.map(|artist| Row::new(vec![Cell::from(artist.clone())]))
+					.collect();
+				let artists = Table::new(artist_rows, [Constraint::Fill(1)])
+					.block({
+						let mut block = Block::default()
+							.title("󰠃 Artists")
+							.title_alignment(Alignment::Center)
+							.borders(Borders::ALL)
+							.border_type(BorderType::Rounded);
+						if artists_has_focus {
+							block = block.border_style(Style::default().fg(hl_color));
+						}
+						block
+					})
+					.row_highlight_style(if artists_has_focus {
+						highlight_style
+					} else {
+						Style::default()
+					})
+					.highlight_symbol(if artists_has_focus { "  " } else { "   " });
+				f.render_stateful_widget(artists, horizontal_chunks[0], &mut app.artist_state);
+			}
 
-			let album_rows: Vec<Row> = app
-				.albums
-				.iter()
-				.map(|album| {
-					let fav_marker =
-						if album.favorite { " │ " } else { "" };
-					Row::new(vec![
-						Cell::from(format!(
-							"{}{}",
-							fav_marker,
-							album.artist.clone()
-						)),
-						Cell::from(album.name.clone()),
-					])
+			// folders
+			if mode == BrowseMode::Folders {
+				let folders_has_focus = matches!(app.active_panel, ActivePanel::Folders);
+				let folder_rows: Vec<Row> = app
+					.folders
+					.visible()
+					.into_iter()
+					.map(|folder| {
+						let arrow = if !folder.has_subfolders {
+							"  "
+						} else if app.folders.is_expanded(&folder.path) {
+							"▾ "
+						} else {
+							"▸ "
+						};
+						let problems = if folder.problems > 0 {
+							Line::styled(
+								format!("! {}", folder.problems),
+								Style::default().fg(Color::Red),
+							)
+						} else {
+							Line::raw("")
+						};
+						Row::new(vec![
+							Cell::from(format!(
+								"{}{}{}",
+								"  ".repeat(folder.depth),
+								arrow,
+								folder.name
+							)),
+							Cell::from(folder.tracks.to_string())
+								.style(Style::default().fg(Color::DarkGray)),
+							Cell::from(problems),
+						])
+					})
+					.collect();
+				let folders = Table::new(
+					folder_rows,
+					[
+						Constraint::Fill(1),   // folder name, indented by depth
+						Constraint::Length(5), // playable files
+						Constraint::Length(5), // unreadable or unsupported files
+					],
+				)
+				.block({
+					let mut block = Block::default()
+						.title(" Folders")
+						.title_alignment(Alignment::Center)
+						.borders(Borders::ALL)
+						.border_type(BorderType::Rounded);
+					if folders_has_focus {
+						block = block.border_style(Style::default().fg(hl_color));
+					}
+					block
 				})
-				.collect();
-
-			let albums = Table::new(
-				album_rows,
-				[
-					Constraint::Length(20),     // artist column fixed width
-					Constraint::Percentage(80), // album column takes remaining space
-				],
-			)
-			.block({
-				let mut block = Block::default()
-					.title("󰀥 Albums")
-					.title_alignment(Alignment::Center)
-					.borders(Borders::ALL)
-					.border_type(ratatui::widgets::BorderType::Rounded);
-				if album_has_focus {
-					block = block.border_style(Style::default().fg(hl_color));
-				}
-				block
-			})
-			.row_highlight_style(if album_has_focus {
-				highlight_style
-			} else {
-				Style::default()
-			})
-			.highlight_symbol(if album_has_focus { "  " } else { "   " })
-			.column_spacing(2);
-
-			f.render_stateful_widget(
-				albums,
-				horizontal_chunks[0],
-				&mut app.album_state,
-			);
+				.row_highlight_style(if folders_has_focus {
+					highlight_style
+				} else {
+					Style::default()
+				})
+				.highlight_symbol(if folders_has_focus { "  " } else { "   " });
+				f.render_stateful_widget(folders, horizontal_chunks[0], &mut app.folder_state);
+			}
 
-			// tracks
-			let tracks_has_focus = matches!(app.active_panel, ActivePanel::Tracks);
+			// playlists
+			if mode == BrowseMode::Playlists {
+				let playlists_has_focus = matches!(app.active_panel, ActivePanel::Playlists);
+				let no_playlists = app.playlists.is_empty() && app.config.smart_playlists().is_empty();
+				let playlist_rows: Vec<Row> = if no_playlists {
+					vec![Row::new(vec![Cell::from("[N] new playlist  [P] new smart playlist")])
+						.style(Style::default().fg(Color::DarkGray))]
+				} else {
+					let saved = app.playlists.iter().map(|playlist| {
+						Row::new(vec![
+							Cell::from(playlist.name.clone()),
+							Cell::from(playlist.entries.len().to_string())
+								.style(Style::default().fg(Color::DarkGray)),
+						])
+					});
+					// smart playlists are only filled when opened, so they have no count
+					let smart = app.config.smart_playlists().iter().map(|smart| {
+						Row::new(vec![
+							Cell::from(smart.name.clone()),
+							Cell::from("smart").style(Style::default().fg(Color::DarkGray)),
+						])
+					});
+					saved.chain(smart).collect()
+				};
+				let playlists = Table::new(
+					playlist_rows,
+					[
+						Constraint::Fill(1),   // name
+						Constraint::Length(5), // entries
+					],
+				)
+				.block({
+					let mut block = Block::default()
+						.title("󰲸 Playlists")
+						.title_alignment(Alignment::Center)
+						.borders(Borders::ALL)
+						.border_type(BorderType::Rounded);
+					if playlists_has_focus {
+						block = block.border_style(Style::default().fg(hl_color));
+					}
+					block
+				})
+				.row_highlight_style(if playlists_has_focus && !no_playlists {
+					highlight_style
+				} else {
+					Style::default()
+				})
+				.highlight_symbol(if playlists_has_focus { "  " } else { "   " });
+				f.render_stateful_widget(playlists, horizontal_chunks[0], &mut app.playlist_state);
 
-			let tracks_rows: Vec<Row> = app
-				.tracks
-				.iter()
-				.map(|track| {
-					let fav_marker =
-						if track.favorite { " │ " } else { "" };
+				// entries of the selected playlist
+				let entries_has_focus = matches!(app.active_panel, ActivePanel::Entries);
+				let title = match app.selected_playlist_name() {
+					Some(name) => format!(" {}", name),
+					None => String::from(" Playlist"),
+				};
+				let smart_rows = app.smart_tracks.iter().map(|track| {
 					Row::new(vec![
-						Cell::from(format!(
-							"{}{}",
-							fav_marker,
-							track.artist.clone()
-						)),
+						Cell::from(track.artist.clone()),
 						Cell::from(track.track_name.clone()),
 						Cell::from(track.album.clone()),
 					])
+				});
+				let entry_rows: Vec<Row> = app
+					.playlist_rows()
+					.into_iter()
+					.map(|(entry, track)| match track {
+						Some(track) => Row::new(vec![
+							Cell::from(track.artist.clone()),
+							Cell::from(track.track_name.clone()),
+							Cell::from(track.album.clone()),
+						]),
+						None => {
+							let reason = if std::path::Path::new(&entry.path).exists() {
+								"not in library"
+							} else {
+								"missing"
+							};
+							Row::new(vec![
+								Cell::from(format!("! {}", reason)),
+								Cell::from(entry.label()),
+								Cell::from(entry.path.clone()),
+							])
+							.style(Style::default().fg(Color::Red))
+						}
+					})
+					.chain(smart_rows) // empty unless a smart playlist is selected
+					.collect();
+				let entries = Table::new(
+					entry_rows,
+					[
+						Constraint::Length(20),     // artist column
+						Constraint::Percentage(60), // track column
+						Constraint::Percentage(20), // album column
+					],
+				)
+				.block({
+					let mut block = Block::default()
+						.title(title)
+						.title_alignment(Alignment::Center)
+						.borders(Borders::ALL)
+						.border_type(BorderType::Rounded);
+					if entries_has_focus {
+						block = block.border_style(Style::default().fg(hl_color));
+					}
+					block
 				})
-				.collect();
+				.row_highlight_style(if entries_has_focus {
+					highlight_style
+				} else {
+					Style::default()
+				})
+				.highlight_symbol(if entries_has_focus { "  " } else { "   " })
+				.column_spacing(2);
+				f.render_stateful_widget(entries, tracks_chunk, &mut app.entry_state);
+			}
+
+			// albums
+			if albums_pct > 0 {
+				let album_has_focus = matches!(app.active_panel, ActivePanel::Albums);
+				let marked = app.marked_rows(ActivePanel::Albums);
+
+				let album_rows: Vec<Row> = app
+					.albums
+					.iter()
+					.enumerate()
+					.map(|(i, album)| {
+						let fav_marker =
+							if album.favorite { " │ " } else { "" };
+						let release_type = album.release_type_label();
+						let row = Row::new(vec![
+							Cell::from(format!(
+								"{}{}",
+								fav_marker,
+								album.artist.clone()
+							)),
+							Cell::from(if release_type.is_empty() || release_type == "Album" {
+								album.name.clone()
+							} else {
+								format!("{} ({})", album.name, release_type)
+							}),
+							Cell::from(if album.year > 0 {
+								album.year.to_string()
+							} else {
+								String::new()
+							})
+							.style(Style::default().fg(Color::DarkGray)),
+							Cell::from(ratings::stars(album.rating)),
+						]);
+						if marked.contains(&i) {
+							row.style(marked_style)
+						} else if album.inferred {
+							row.style(inferred_style)
+						} else {
+							row
+						}
+					})
+					.collect();
+
+				let albums = Table::new(
+					album_rows,
+					[
+						Constraint::Length(20), // artist column fixed width
+						Constraint::Fill(1),    // album column takes remaining space
+						Constraint::Length(4),  // year column
+						Constraint::Length(5),  // rating column
+					],
+				)
+				.block({
+					let mut block = Block::default()
+						.title(marked_title("󰀥 Albums", marked.len()))
+						.title_alignment(Alignment::Center)
+						.borders(Borders::ALL)
+						.border_type(ratatui::widgets::BorderType::Rounded);
+					if album_has_focus {
+						block = block.border_style(Style::default().fg(hl_color));
+					}
+					block
+				})
+				.row_highlight_style(if album_has_focus {
+					highlight_style
+				} else {
+					Style::default()
+				})
+				.highlight_symbol(if album_has_focus { "  " } else { "   " })
+				.column_spacing(2);
+
+				f.render_stateful_widget(
+					albums,
+					albums_chunk,
+					&mut app.album_state,
+				);
+			}
+
+			// tracks
+			if mode != BrowseMode::Playlists {
+				let tracks_has_focus = matches!(app.active_panel, ActivePanel::Tracks);
+				let marked = app.marked_rows(ActivePanel::Tracks);
+				let show_play_stats = app.config.show_play_stats();
+				let now = history::now();
 
-			let tracks = Table::new(
-				tracks_rows,
-				[
-					Constraint::Length(20),     // artist column
-					Constraint::Percentage(60), // track column
-					Constraint::Percentage(20), // album column
-				],
-			)
-			.block({
-				let mut block = Block::default()
-					.title(" Tracks")
+				let tracks_rows: Vec<Row> = app
+					.tracks
+					.iter()
+					.enumerate()
+					.map(|(i, track)| {
+						let fav_marker =
+							if track.favorite { " │ " } else { "" };
+						let field_style = |inferred: bool| {
+							if marked.contains(&i) {
+								marked_style
+							} else if inferred {
+								inferred_style
+							} else {
+								Style::default()
+							}
+						};
+						let mut cells = vec![
+							Line::styled(
+								format!("{}{}", fav_marker, track.artist.clone()),
+								field_style(track.inferred.artist),
+							),
+							Line::styled(
+								track.track_name.clone(),
+								field_style(track.inferred.title),
+							),
+							Line::styled(
+								track.album.clone(),
+								field_style(track.inferred.album),
+							),
+							Line::styled(ratings::stars(track.rating), field_style(false)),
+						];
+						if show_play_stats {
+							let dim = if marked.contains(&i) {
+								marked_style
+							} else {
+								Style::default().fg(Color::DarkGray)
+							};
+							cells.extend([
+								Line::styled(track.plays.to_string(), dim),
+								Line::styled(track.skips.to_string(), dim),
+								Line::styled(
+									if track.last_played > 0 {
+										history::format_ago(track.last_played, now)
+									} else {
+										String::from("never")
+									},
+									dim,
+								),
+							]);
+						}
+						match load_album_and_track_lists::disc_separator(&app.tracks, i) {
+							Some(disc) => Row::new(cells.into_iter().enumerate().map(
+								|(col, cell)| {
+									let sep = if col == 0 {
+										Line::styled(
+											format!("── {} ", disc),
+											Style::default().fg(Color::DarkGray),
+										)
+									} else {
+										Line::raw("")
+									};
+									Cell::from(Text::from(vec![sep, cell]))
+								},
+							))
+							.height(2),
+							None => Row::new(cells),
+						}
+					})
+					.collect();
+
+				let widths = if show_play_stats {
+					vec![
+						Constraint::Length(15), // artist column
+						Constraint::Fill(2),    // track column
+						Constraint::Fill(1),    // album column
+						Constraint::Length(5),  // rating
+						Constraint::Length(5),  // plays
+						Constraint::Length(5),  // skips
+						Constraint::Length(10), // last played
+					]
+				} else {
+					vec![
+						Constraint::Length(20),     // artist column
+						Constraint::Percentage(60), // track column
+						Constraint::Percentage(20), // album column
+						Constraint::Length(5),      // rating column
+					]
+				};
+				let mut tracks = Table::new(tracks_rows, widths);
+				if show_play_stats {
+					tracks = tracks.header(
+						Row::new(vec!["Artist", "Title", "Album", "Stars", "Plays", "Skips", "Played"])
+							.style(Style::default().fg(Color::DarkGray)),
+					);
+				}
+				let tracks = tracks
+				.block({
+					let mut block = Block::default()
+						.title(marked_title(" Tracks", marked.len()))
+						.title_alignment(Alignment::Center)
+						.borders(Borders::ALL)
+						.border_type(ratatui::widgets::BorderType::Rounded);
+					if tracks_has_focus {
+						block = block.border_style(Style::default().fg(hl_color));
+					}
+					block
+				})
+				.row_highlight_style(if tracks_has_focus {
+					highlight_style
+				} else {
+					Style::default()
+				})
+				.highlight_symbol(if tracks_has_focus { "  " } else { "   " })
+				.column_spacing(2);
+
+				f.render_stateful_widget(
+					tracks,
+					tracks_chunk,
+					&mut app.track_state,
+				);
+			}
+
+			// lyrics
+			if show_lyrics {
+				app.refresh_lyrics();
+				let title = match &app.lyrics {
+					Some(lyrics) => format!("󰎈 Lyrics ({})", lyrics.source),
+					None => String::from("󰎈 Lyrics"),
+				};
+				let block = Block::default()
+					.title(title)
 					.title_alignment(Alignment::Center)
 					.borders(Borders::ALL)
-					.border_type(ratatui::widgets::BorderType::Rounded);
-				if tracks_has_focus {
-					block = block.border_style(Style::default().fg(hl_color));
-				}
-				block
-			})
-			.row_highlight_style(if tracks_has_focus {
-				highlight_style
-			} else {
-				Style::default()
-			})
-			.highlight_symbol(if tracks_has_focus { "  " } else { "   " })
-			.column_spacing(2);
-
-			f.render_stateful_widget(
-				tracks,
-				horizontal_chunks[1],
-				&mut app.track_state,
-			);
+					.border_type(BorderType::Rounded);
+				let inner = block.inner(lyrics_chunk);
+				f.render_widget(block, lyrics_chunk);
+				let paragraph = match &app.lyrics {
+					Some(lyrics) => {
+						let current = lyrics.current_line(app.player.position());
+						let lines: Vec<Line> = lyrics
+							.lines
+							.iter()
+							.enumerate()
+							.map(|(i, line)| {
+								if Some(i) == current {
+									Line::styled(
+										line.text.clone(),
+										Style::default().fg(hl_color).add_modifier(Modifier::BOLD),
+									)
+								} else {
+									Line::raw(line.text.clone())
+								}
+							})
+							.collect();
+						// synced lyrics keep the current line in the middle
+						let scroll = match current {
+							Some(i) => lyrics
+								.rows_before(i, inner.width as usize)
+								.saturating_sub(inner.height as usize / 2),
+							None if lyrics.synced => 0,
+							None => lyrics.rows_before(app.lyrics_scroll, inner.width as usize),
+						};
+						Paragraph::new(lines).scroll((scroll as u16, 0))
+					}
+					None => Paragraph::new(if app.player.current_track().is_some() {
+						"\nNo lyrics for this track"
+					} else {
+						"\nNo track"
+					})
+					.style(Style::default().fg(Color::DarkGray)),
+				};
+				f.render_widget(
+					paragraph
+						.alignment(Alignment::Center)
+						.wrap(Wrap { trim: true }),
+					inner,
+				);
+			}
 
 			// find
 			let find = Paragraph::new(app.input.as_str())
@@ -196,30 +639,50 @@ fn main() -> std::io::Result<()> {
 
 			// queue
 			let queue_has_focus = matches!(app.active_panel, ActivePanel::Queue);
+			let marked = app.marked_rows(ActivePanel::Queue);
 			let queue_items: Vec<ListItem> = app
 				.queue
 				.iter()
 				.enumerate()
 				.map(|(i, track)| {
-					let mut item = ListItem::new(format!(
+					let mut lines = Vec::new();
+					if let Some(disc) =
+						load_album_and_track_lists::disc_separator(&app.queue, i)
+					{
+						lines.push(Line::styled(
+							format!("── {} ──", disc),
+							Style::default().fg(Color::DarkGray),
+						));
+					}
+					lines.push(Line::raw(format!(
 						"{}. {} - {} [{}]",
-						track.track_num,
+						track.number_label(),
 						track.artist,
 						track.track_name,
 						track.album
-					));
+					)));
+					let mut item = ListItem::new(Text::from(lines));
 					if Some(i) == app.queue_index {
 						item = item.style(Style::default()
 							.add_modifier(Modifier::BOLD)
 							.bg(Color::DarkGray));
 					}
+					if marked.contains(&i) {
+						item = item.style(marked_style);
+					}
 					item
 				})
 				.collect();
 			let queue = List::new(queue_items)
 				.block({
 					let mut block = Block::default()
-						.title("󰲹 Queue")
+						.title(marked_title(
+							&match app.config.auto_dj() {
+								AutoDj::Off => String::from("󰲹 Queue"),
+								mode => format!("󰲹 Queue │ auto-DJ: {}", mode.label()),
+							},
+							marked.len(),
+						))
 						.title_alignment(ratatui::layout::Alignment::Center)
 						.borders(Borders::ALL)
 						.border_type(BorderType::Rounded);
@@ -238,35 +701,68 @@ fn main() -> std::io::Result<()> {
 				.highlight_symbol(if queue_has_focus { "  " } else { "   " });
 			f.render_stateful_widget(queue, queue_logo_chunk[1], &mut app.queue_state);
 
-			// logo
-			let logo_text = format!(
-				"\n░█▀▀░▀█▀░▀█▀░█▄█\n░▀▀█░░█░░░█░░█░█\n░▀▀▀░░▀░░▀▀▀░▀░▀\nv{}\n{}\n{}\n",
-				APP_VER, WEBSITE, COPYRIGHT
-			);
-			let centered_lines: String = logo_text
-				.lines()
-				.map(|line| {
-					let total_padding = (queue_logo_chunk[1].width as usize)
-						.saturating_sub(line.chars().count());
-					let left_padding = total_padding / 2;
-					let right_padding = total_padding - left_padding;
-					format!(
-						"{}{}{}\n",
-						" ".repeat(left_padding), // ░
-						line,
-						" ".repeat(right_padding)
-					)
-				})
-				.collect();
-			let logo = Paragraph::new(centered_lines)
-				.style(Style::default().fg(hl_color))
-				.alignment(Alignment::Left);
-			f.render_widget(logo, queue_logo_chunk[2]);
+			// cover or logo
+			if app.config.show_cover() {
+				let block = Block::default()
+					.title("󰝚 Cover")
+					.title_alignment(Alignment::Center)
+					.borders(Borders::ALL)
+					.border_type(BorderType::Rounded);
+				let inner = block.inner(queue_logo_chunk[2]);
+				f.render_widget(block, queue_logo_chunk[2]);
+				let track = app.cover_track();
+				match track.and_then(|track| app.covers.get(&track)) {
+					Some(cover) => {
+						// centered horizontally, the height is what limits it most of the time
+						let size = cover.size_for(Resize::Fit(None), inner);
+						let area = Rect {
+							x: inner.x + inner.width.saturating_sub(size.width) / 2,
+							y: inner.y,
+							..size
+						};
+						f.render_stateful_widget(StatefulImage::default(), area, cover);
+					}
+					None => f.render_widget(no_cover(), inner),
+				}
+			} else {
+				// logo
+				let logo_text = format!(
+					"\n░█▀▀░▀█▀░▀█▀░█▄█\n░▀▀█░░█░░░█░░█░█\n░▀▀▀░░▀░░▀▀▀░▀░▀\nv{}\n{}\n{}\n",
+					APP_VER, WEBSITE, COPYRIGHT
+				);
+				let centered_lines: String = logo_text
+					.lines()
+					.map(|line| {
+						let total_padding = (queue_logo_chunk[1].width as usize)
+							.saturating_sub(line.chars().count());
+						let left_padding = total_padding / 2;
+						let right_padding = total_padding - left_padding;
+						format!(
+							"{}{}{}\n",
+							" ".repeat(left_padding), // ░
+							line,
+							" ".repeat(right_padding)
+						)
+					})
+					.collect();
+				let logo = Paragraph::new(centered_lines)
+					.style(Style::default().fg(hl_color))
+					.alignment(Alignment::Left);
+				f.render_widget(logo, queue_logo_chunk[2]);
+			}
 
 			// player
 			let player_timeline_str = app.update_player_timeline(vertical_chunks[0]);
 			let total_width = vertical_chunks[0].width as usize;
-			let right = format!("󰕾 {}%", app.player.get_volume_as_percentage());
+			let mut right_parts = Vec::new();
+			if let Some(track) = &app.player.current_track {
+				right_parts.push(track.audio.summary());
+			}
+			if !app.scan_issues.is_empty() {
+				right_parts.push(format!("! {} issues", app.scan_issues.len()));
+			}
+			right_parts.push(format!("󰕾 {}%", app.player.get_volume_as_percentage()));
+			let right = right_parts.join(" │ ");
 			let max_left = total_width.saturating_sub(right.chars().count() + 2);
 			let mut left_full = format!(
 				"{} │ {}",
@@ -274,7 +770,7 @@ fn main() -> std::io::Result<()> {
 				if let Some(track) = app.player.current_track() {
 					format!(
 						"{}. {} - {} [{}]",
-						track.track_num,
+						track.number_label(),
 						track.artist,
 						track.track_name,
 						track.album
@@ -306,6 +802,7 @@ fn main() -> std::io::Result<()> {
 			let player_ui = Paragraph::new(Text::raw(player_ui_text))
 				.style(Style::default().fg(app.highlight_color))
 				.block(Block::default()
+					.title(app.status.clone().map(|s| format!(" {} ", s)).unwrap_or_default())
 					.borders(Borders::ALL)
 					.border_type(BorderType::Rounded)
 					.border_style(Style::default().fg(Color::White)));
@@ -320,84 +817,202 @@ fn main() -> std::io::Result<()> {
 					queue_logo_chunk[0].y + 1,
 				)),
 			}
+
+			// popup
+			if let Some(popup) = app.popup {
+				popups::draw(f, &mut app, popup);
+			}
 			app.load_next_track_if_current_ends();
 		})?;
 
 		// event handling
 		let current_vol = app.player.get_volume();
-		if event::poll(std::time::Duration::from_millis(100))? {
-			if let Event::Key(key) = event::read()? {
-				match app.input_mode {
-					InputMode::Normal => match key.code {
-						c if K_LEFT.contains(&c) => app.move_left(),
-						c if K_RIGHT.contains(&c) => app.move_right(),
-						c if K_DOWN.contains(&c) => app.move_down(),
-						c if K_UP.contains(&c) => app.move_up(),
-
-						K_QUIT => break,
-						K_FIND => {
-							if !app.input.is_empty() {
-								app.input = "".to_string();
-							}
-							app.input_mode = InputMode::Find;
+		if event::poll(std::time::Duration::from_millis(100))?
+			&& let Event::Key(key) = event::read()?
+		{
+			app.status = None;
+			if let Some(Popup::TagEditor) = app.popup {
+				if key.kind == KeyEventKind::Press
+					&& let Some(editor) = &mut app.tag_editor
+				{
+					match key.code {
+						KeyCode::Enter => app.save_tag_editor(),
+						KeyCode::Esc => app.close_popup(),
+						KeyCode::Tab | KeyCode::Down => editor.next_field(),
+						KeyCode::BackTab | KeyCode::Up => editor.prev_field(),
+						KeyCode::Left => editor.move_cursor_left(),
+						KeyCode::Right => editor.move_cursor_right(),
+						KeyCode::Backspace => editor.delete_char(),
+						KeyCode::Char(c) => editor.enter_char(c),
+						_ => {}
+					}
+				}
+				continue;
+			}
+			if let Some(Popup::Prompt(kind)) = app.popup {
+				if key.kind == KeyEventKind::Press {
+					match key.code {
+						KeyCode::Enter => app.undoable(|app| app.submit_prompt(kind)),
+						KeyCode::Esc => app.close_popup(),
+						KeyCode::Left => app.prompt.move_cursor_left(),
+						KeyCode::Right => app.prompt.move_cursor_right(),
+						KeyCode::Backspace => app.prompt.delete_char(),
+						KeyCode::Char(c) => app.prompt.enter_char(c),
+						_ => {}
+					}
+				}
+				continue;
+			}
+			if let Some(popup) = app.popup {
+				match key.code {
+					c if K_DOWN.contains(&c) => app.popup_move_down(),
+					c if K_UP.contains(&c) => app.popup_move_up(),
+					K_CLOSE_POPUP | K_QUIT => app.close_popup(),
+					K_ISSUES if popup == Popup::LibraryIssues => app.close_popup(),
+					K_DUPLICATES if popup == Popup::Duplicates => app.close_popup(),
+					K_VERIFY if popup == Popup::Duplicates => {
+						app.verify_selected_duplicate()
+					}
+					K_CONFIRM if popup == Popup::BulkEditConfirm => app.apply_bulk_edit(),
+					K_CONFIRM if popup == Popup::OrganizePreview => app.run_organize(),
+					K_CONFIRM if popup == Popup::Filters => app.apply_selected_filter(),
+					K_FILTERS if popup == Popup::Filters => app.close_popup(),
+					K_STATS if popup == Popup::Stats => app.close_popup(),
+					K_DETAILS if popup == Popup::Details => app.close_popup(),
+					K_CONFIRM if popup == Popup::AddToPlaylist => app.add_to_selected_playlist(),
+					K_CONFIRM if popup == Popup::DeletePlaylist => app.delete_playlist(),
+					K_CONFIRM if popup == Popup::ExportQueue => app.choose_export(),
+					K_HISTORY if popup == Popup::History => app.close_popup(),
+					K_MAIN if popup == Popup::History => app.undoable(App::queue_history_track),
+					K_AUX if popup == Popup::History => app.undoable(App::queue_history_album),
+					_ => {}
+				}
+				continue;
+			}
+			match app.input_mode {
+				InputMode::Normal => match key.code {
+					c if K_LEFT.contains(&c) => app.move_left(),
+					c if K_RIGHT.contains(&c) => app.move_right(),
+					c if K_DOWN.contains(&c) => app.move_down(),
+					c if K_UP.contains(&c) => app.move_up(),
+
+					K_QUIT => break,
+					K_FIND => {
+						if !app.input.is_empty() {
+							app.input = "".to_string();
 						}
-						K_CLEAR_FIND => app.clear_find(),
-						K_FAVORITE => {
-							app.toggle_favorite();
-							app.config.save(&config_path);
+						app.input_mode = InputMode::Find;
+					}
+					K_CLEAR_FIND => app.clear_find(),
+					K_FAVORITE => {
+						app.undoable(App::toggle_favorite);
+						app.config.save(&config_path);
+					}
+					K_CLEAR_FAV => app.undoable(App::clear_all_favorites_in_app),
+					key if K_RATE.contains(&key) => {
+						if let Some(stars) = K_RATE.iter().position(|k| *k == key) {
+							app.rate(stars as u8);
 						}
-						K_CLEAR_FAV => app.clear_all_favorites_in_app(),
-						K_PLAY => app.player.toggle_play(),
+					}
+					K_UNDO => app.undo(),
+					K_REDO => app.redo(),
+					K_PLAY => app.player.toggle_play(),
+
+					K_SORT => app.toggle_sort(),
+					K_SORT_KEY => app.cycle_sort_key(),
+					K_FILTERS => app.toggle_popup(Popup::Filters),
 
-						K_SORT => app.toggle_sort(),
+					K_N_TRK => app.next_track(),
+					K_P_TRK => app.prev_track(),
+
+					K_VOL_DOWN => {
+						app.player.set_volume(current_vol - 0.1)
+					}
+					K_VOL_UP => {
+						app.player.set_volume(current_vol + 0.1)
+					}
+					K_HL => app.rotate_hl_color(),
+					K_ISSUES => app.toggle_popup(Popup::LibraryIssues),
+					K_DUPLICATES => app.toggle_popup(Popup::Duplicates),
+					K_STATS => app.toggle_popup(Popup::Stats),
+					K_HISTORY => app.toggle_popup(Popup::History),
+					K_DETAILS => app.open_details(),
+					K_EDIT_TAGS => app.open_tag_editor(),
+					K_ORGANIZE => app.open_organize(),
+					K_COVER => app.toggle_cover(),
+					K_LYRICS => app.toggle_lyrics(),
+					K_PLAY_STATS => app.toggle_play_stats(),
+					K_BROWSE_MODE => app.toggle_browse_mode(),
+					K_OPEN => app.undoable(App::open_selected),
+					K_NEW_PLAYLIST => app.open_new_playlist(),
+					K_NEW_SMART_PLAYLIST => app.open_new_smart_playlist(),
+					K_AUTO_DJ => app.toggle_auto_dj(),
+					K_RENAME => app.open_rename_playlist(),
+					K_DELETE => app.confirm_delete(),
+					K_ADD_TO_PLAYLIST => app.open_add_to_playlist(),
+					K_MOVE_UP => app.undoable(|app| app.move_selected(-1)),
+					K_MOVE_DOWN => app.undoable(|app| app.move_selected(1)),
+					K_MOVE_TO_END => app.undoable(App::move_to_end),
+					K_EXPORT_QUEUE => app.open_export_queue(),
+					K_IMPORT_QUEUE => app.open_import_queue(),
+					K_LYRICS_UP => app.scroll_lyrics_up(),
+					K_LYRICS_DOWN => app.scroll_lyrics_down(),
+					K_BULK_EDIT => {
+						let tracks = app.bulk_selection();
+						if !tracks.is_empty() {
+							let edited = edit_in_external_editor(
+								&mut terminal,
+								&bulk_edit::dump(&tracks),
+							);
+							match edited {
+								Ok(Some(contents)) => app.review_bulk_edit(&contents, &tracks),
+								Ok(None) => {
+									app.status = Some(String::from("Editor exited with an error"))
+								}
+								Err(err) => app.status = Some(err),
+							}
+						}
+					}
 
-						K_N_TRK => app.next_track(),
-						K_P_TRK => app.prev_track(),
+					// queue
+					K_CLEAR if app.has_marks() => app.clear_marks(),
+					K_CLEAR => app.undoable(App::clear_queue),
+					K_MARK => app.toggle_mark(),
+					K_VISUAL => app.toggle_visual(),
+					K_SHUFFLE => app.undoable(App::shuffle_queue),
+					K_ADD_ALL_TRACKS => app.undoable(App::add_all_tracks_to_queue),
+					K_MAIN => app.undoable(App::main_action),
+					K_AUX => app.undoable(App::aux_main_action),
+					_ => {}
+				},
 
-						K_VOL_DOWN => {
-							app.player.set_volume(current_vol - 0.1)
+				InputMode::Find if key.kind == KeyEventKind::Press => {
+					match key.code {
+						KeyCode::Enter => app.submit_find(),
+						KeyCode::Char(to_insert) => {
+							app.enter_char(to_insert);
+							app.find_term = app.input.clone();
+							app.find_albums();
+							app.find_tracks();
 						}
-						K_VOL_UP => {
-							app.player.set_volume(current_vol + 0.1)
+						KeyCode::Backspace => {
+							app.delete_char();
+							app.find_albums();
+							app.find_tracks();
 						}
-						K_HL => app.rotate_hl_color(),
-
-						// queue
-						K_CLEAR => app.clear_queue(),
-						K_SHUFFLE => app.shuffle_queue(),
-						K_ADD_ALL_TRACKS => app.add_all_tracks_to_queue(),
-						K_MAIN => app.main_action(),
-						K_AUX => app.aux_main_action(),
-						_ => {}
-					},
-
-					InputMode::Find if key.kind == KeyEventKind::Press => {
-						match key.code {
-							KeyCode::Enter => app.submit_find(),
-							KeyCode::Char(to_insert) => {
-								app.enter_char(to_insert);
-								app.find_term = app.input.clone();
-								app.find_albums();
-								app.find_tracks();
-							}
-							KeyCode::Backspace => {
-								app.delete_char();
-								app.find_albums();
-								app.find_tracks();
-							}
-							KeyCode::Left => app.move_cursor_left(),
-							KeyCode::Right => app.move_cursor_right(),
-							KeyCode::Esc => {
-								app.input_mode = InputMode::Normal;
-							}
-							_ => {}
+						KeyCode::Left => app.move_cursor_left(),
+						KeyCode::Right => app.move_cursor_right(),
+						KeyCode::Esc => {
+							app.input_mode = InputMode::Normal;
 						}
+						_ => {}
 					}
-					InputMode::Find => {}
 				}
+				InputMode::Find => {}
 			}
 		}
 	}
+	app.finish_play();
 	app.config.set_color(app.highlight_color);
 	app.config.save(&config_path);
 
@@ -406,3 +1021,71 @@ fn main() -> std::io::Result<()> {
 	execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
 	Ok(())
 }
+
+/// A new file with a random name in the temp directory, only readable by us.
+/// Never opens an existing file, so a symlink planted in a shared /tmp
+/// can't redirect the write.
+fn create_temp_file(
+	prefix: &str,
+	extension: &str,
+) -> std::io::Result<(std::path::PathBuf, std::fs::File)> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	loop {
+		let name = format!("{}-{:016x}.{}", prefix, rand::random::<u64>(), extension);
+		let path = std::env::temp_dir().join(name);
+		match options.open(&path) {
+			Ok(file) => return Ok((path, file)),
+			Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+fn no_cover() -> Paragraph<'static> {
+	Paragraph::new("\nNo cover")
+		.style(Style::default().fg(Color::DarkGray))
+		.alignment(Alignment::Center)
+}
+
+/// Suspend the TUI, let the user edit `contents` in $EDITOR and return the
+/// result, or `None` if the editor failed. Other errors come back as a
+/// message for the status line; the TUI is restored either way.
+fn edit_in_external_editor(
+	terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+	contents: &str,
+) -> Result<Option<String>, String> {
+	let (path, mut file) =
+		create_temp_file("stim-tags", "tsv").map_err(|e| format!("Couldn't create a temp file: {}", e))?;
+	let written = std::io::Write::write_all(&mut file, contents.as_bytes());
+	drop(file);
+	if let Err(e) = written {
+		std::fs::remove_file(&path).ok();
+		return Err(format!("Couldn't write {}: {}", path.display(), e));
+	}
+
+	disable_raw_mode().ok();
+	execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
+	let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+	// run through the shell so EDITOR may carry arguments, e.g. "code -w"
+	let status = std::process::Command::new("sh")
+		.arg("-c")
+		.arg(format!("{} \"$1\"", editor))
+		.arg("sh")
+		.arg(&path)
+		.status();
+	enable_raw_mode().ok();
+	execute!(terminal.backend_mut(), EnterAlternateScreen).ok();
+	terminal.clear().ok();
+
+	let edited = match status {
+		Ok(status) if status.success() => std::fs::read_to_string(&path)
+			.map(Some)
+			.map_err(|e| format!("Couldn't read the edited file: {}", e)),
+		_ => Ok(None),
+	};
+	std::fs::remove_file(&path).ok();
+	edited
+}
@@ -0,0 +1,234 @@
+use crate::load_album_and_track_lists::Track;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// per-track sidecars share the audio file's stem
+const TRACK_SIDECARS: [&str; 2] = ["lrc", "cue"];
+// per-folder sidecars move with the album once every track has left the folder
+const FOLDER_SIDECARS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "cue"];
+
+/// A file move computed from a track's tags.
+#[derive(Debug, Clone)]
+pub struct PlannedMove {
+	pub from: PathBuf,
+	pub to: PathBuf,
+	pub sidecars: Vec<(PathBuf, PathBuf)>,
+	pub collision: Option<String>,
+}
+
+/// Replace characters that are awkward or invalid in file names.
+fn sanitize(component: &str) -> String {
+	let cleaned: String = component
+		.chars()
+		.map(|c| match c {
+			'/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '\0' => '_',
+			c => c,
+		})
+		.collect();
+	let cleaned = cleaned.trim().trim_matches('.').trim().to_string();
+	if cleaned.is_empty() {
+		String::from("_")
+	} else {
+		cleaned
+	}
+}
+
+/// Fill in a template like `{albumartist}/{year} - {album}/{disc}{track:02} - {title}.{ext}`.
+///
+/// `{disc}` is empty on single-disc albums. A `:0N` suffix zero-pads numbers.
+pub fn render_template(template: &str, track: &Track) -> PathBuf {
+	let ext = Path::new(&track.path)
+		.extension()
+		.map(|e| e.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+	let mut path = PathBuf::new();
+	for segment in template.split('/').filter(|s| !s.is_empty()) {
+		let mut out = String::new();
+		let mut rest = segment;
+		while let Some(start) = rest.find('{') {
+			let Some(len) = rest[start..].find('}') else {
+				break;
+			};
+			out.push_str(&rest[..start]);
+			let spec = &rest[start + 1..start + len];
+			let (name, width) = match spec.split_once(':') {
+				Some((name, pad)) => (name, pad.trim_start_matches('0').parse::<usize>().ok()),
+				None => (spec, None),
+			};
+			let number = |n: i32| match width {
+				Some(w) => format!("{:0w$}", n, w = w),
+				None => n.to_string(),
+			};
+			let value = match name.to_lowercase().as_str() {
+				"albumartist" => track.album_artist.clone(),
+				"artist" => track.artist.clone(),
+				"album" => track.album.clone(),
+				"title" => track.track_name.clone(),
+				"genre" => track.genres.first().cloned().unwrap_or_default(),
+				"year" if track.year > 0 => track.year.to_string(),
+				"year" => String::from("0000"),
+				"track" => number(track.track_num),
+				"disc" if track.is_multi_disc() => number(track.disc_num),
+				"disc" => String::new(),
+				"ext" => ext.clone(),
+				_ => String::new(),
+			};
+			out.push_str(&value);
+			rest = &rest[start + len + 1..];
+		}
+		out.push_str(rest);
+		path.push(sanitize(&out));
+	}
+	path
+}
+
+/// Compute where every track should go, flagging collisions instead of
+/// resolving them. Tracks already in the right place are left out.
+pub fn plan(tracks: &[Track], root: &Path, template: &str) -> Vec<PlannedMove> {
+	let mut moves: Vec<PlannedMove> = tracks
+		.iter()
+		.map(|track| {
+			let from = PathBuf::from(&track.path);
+			let to = root.join(render_template(template, track));
+			let sidecars = TRACK_SIDECARS
+				.iter()
+				.map(|ext| (from.with_extension(ext), to.with_extension(ext)))
+				.filter(|(sidecar, _)| sidecar.is_file())
+				.collect();
+			PlannedMove {
+				from,
+				to,
+				sidecars,
+				collision: None,
+			}
+		})
+		.filter(|m| m.from != m.to)
+		.collect();
+
+	// several tracks with the same target
+	let mut targets: HashMap<PathBuf, usize> = HashMap::new();
+	for m in &moves {
+		*targets.entry(m.to.clone()).or_default() += 1;
+	}
+	let sources: HashSet<PathBuf> = moves.iter().map(|m| m.from.clone()).collect();
+	for m in moves.iter_mut() {
+		if targets[&m.to] > 1 {
+			m.collision = Some(String::from("several tracks map to this path"));
+		} else if m.to.exists() && !sources.contains(&m.to) {
+			m.collision = Some(String::from("target already exists"));
+		}
+	}
+
+	add_folder_sidecars(&mut moves, tracks);
+	moves
+}
+
+/// Move cover images and cue sheets along with an album when all audio in
+/// their folder goes to the same new folder.
+fn add_folder_sidecars(moves: &mut [PlannedMove], tracks: &[Track]) {
+	let mut folders: HashMap<PathBuf, HashSet<PathBuf>> = HashMap::new();
+	for m in moves.iter() {
+		if let (Some(from_dir), Some(to_dir)) = (m.from.parent(), m.to.parent()) {
+			folders
+				.entry(from_dir.to_path_buf())
+				.or_default()
+				.insert(to_dir.to_path_buf());
+		}
+	}
+	for (from_dir, to_dirs) in folders {
+		if to_dirs.len() != 1 {
+			continue;
+		}
+		let moving_all = tracks
+			.iter()
+			.filter(|t| Path::new(&t.path).parent() == Some(from_dir.as_path()))
+			.all(|t| moves.iter().any(|m| m.from == Path::new(&t.path) && m.collision.is_none()));
+		let Some(to_dir) = to_dirs.into_iter().next() else {
+			continue;
+		};
+		if !moving_all || to_dir == from_dir {
+			continue;
+		}
+		let Ok(entries) = fs::read_dir(&from_dir) else {
+			continue;
+		};
+		let claimed: HashSet<PathBuf> = moves
+			.iter()
+			.flat_map(|m| m.sidecars.iter().map(|(s, _)| s.clone()))
+			.collect();
+		let folder_sidecars: Vec<(PathBuf, PathBuf)> = entries
+			.filter_map(Result::ok)
+			.map(|e| e.path())
+			.filter(|p| p.is_file() && !claimed.contains(p))
+			.filter(|p| {
+				p.extension()
+					.map(|e| e.to_string_lossy().to_lowercase())
+					.is_some_and(|e| FOLDER_SIDECARS.contains(&e.as_str()))
+			})
+			.filter_map(|p| {
+				let name = p.file_name()?.to_owned();
+				Some((p, to_dir.join(name)))
+			})
+			.collect();
+		if let Some(first) = moves.iter_mut().find(|m| m.from.parent() == Some(from_dir.as_path())) {
+			first.sidecars.extend(folder_sidecars);
+		}
+	}
+}
+
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+	if let Some(dir) = to.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	// rename fails across filesystems, fall back to copy + remove
+	if fs::rename(from, to).is_err() {
+		fs::copy(from, to)?;
+		fs::remove_file(from)?;
+	}
+	Ok(())
+}
+
+/// Remove `dir` and its parents up to `root` while they are empty.
+fn remove_empty_dirs(dir: &Path, root: &Path) {
+	let mut dir = Some(dir);
+	while let Some(d) = dir {
+		if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+			break;
+		}
+		dir = d.parent();
+	}
+}
+
+/// Carry out the moves that have no collision, cleaning up emptied folders
+/// inside `library_dir`. Returns the audio files that were moved as
+/// (old, new) paths, and an error message per failed move.
+pub fn execute(moves: &[PlannedMove], library_dir: &Path) -> (Vec<(String, String)>, Vec<String>) {
+	let mut moved = Vec::new();
+	let mut errors = Vec::new();
+	for m in moves.iter().filter(|m| m.collision.is_none()) {
+		if m.to.exists() {
+			errors.push(format!("{}: target already exists", m.to.display()));
+			continue;
+		}
+		if let Err(e) = move_file(&m.from, &m.to) {
+			errors.push(format!("{}: {}", m.from.display(), e));
+			continue;
+		}
+		for (from, to) in &m.sidecars {
+			if !to.exists()
+				&& let Err(e) = move_file(from, to)
+			{
+				errors.push(format!("{}: {}", from.display(), e));
+			}
+		}
+		moved.push((
+			m.from.to_string_lossy().to_string(),
+			m.to.to_string_lossy().to_string(),
+		));
+		if let Some(dir) = m.from.parent() {
+			remove_empty_dirs(dir, library_dir);
+		}
+	}
+	(moved, errors)
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Literal(String),
+	Field(String),
+}
+
+/// Match a library-relative path against a template such as
+/// `{artist}/{album}/{track} - {title}` and return the captured fields.
+///
+/// The last template segment is matched against the file stem and the
+/// preceding segments against the parent directories, aligned from the end.
+/// Segments that don't match are skipped, so a partially matching path still
+/// yields whatever fields could be read.
+pub fn infer_fields(template: &str, rel_path: &Path) -> HashMap<String, String> {
+	let mut fields = HashMap::new();
+
+	let mut components: Vec<String> = rel_path
+		.parent()
+		.map(|p| {
+			p.components()
+				.filter_map(|c| c.as_os_str().to_str())
+				.map(str::to_string)
+				.collect()
+		})
+		.unwrap_or_default();
+	if let Some(stem) = rel_path.file_stem().and_then(|s| s.to_str()) {
+		components.push(stem.to_string());
+	}
+
+	let segments: Vec<&str> = template.split('/').filter(|s| !s.is_empty()).collect();
+	for (segment, component) in segments.iter().rev().zip(components.iter().rev()) {
+		let tokens = tokenize(segment);
+		let mut captured = Vec::new();
+		if match_tokens(&tokens, component, &mut captured) {
+			for (name, value) in captured {
+				let value = value.trim();
+				if !value.is_empty() {
+					fields.entry(name).or_insert_with(|| value.to_string());
+				}
+			}
+		}
+	}
+	fields
+}
+
+fn tokenize(segment: &str) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	let mut rest = segment;
+	while let Some(start) = rest.find('{') {
+		let Some(len) = rest[start..].find('}') else {
+			break;
+		};
+		if start > 0 {
+			tokens.push(Token::Literal(rest[..start].to_string()));
+		}
+		tokens.push(Token::Field(rest[start + 1..start + len].to_lowercase()));
+		rest = &rest[start + len + 1..];
+	}
+	if !rest.is_empty() {
+		tokens.push(Token::Literal(rest.to_string()));
+	}
+	tokens
+}
+
+/// Backtracking matcher; fields take the shortest non-empty text that lets
+/// the rest of the segment match.
+fn match_tokens(tokens: &[Token], text: &str, captured: &mut Vec<(String, String)>) -> bool {
+	match tokens.split_first() {
+		None => text.is_empty(),
+		Some((Token::Literal(lit), rest)) => {
+			text.starts_with(lit.as_str()) && match_tokens(rest, &text[lit.len()..], captured)
+		}
+		Some((Token::Field(name), rest)) => {
+			for (end, _) in text.char_indices().skip(1).chain([(text.len(), ' ')]) {
+				let value = &text[..end];
+				if value.is_empty() {
+					continue;
+				}
+				if is_numeric_field(name) && value.trim().parse::<i32>().is_err() {
+					continue;
+				}
+				let len = captured.len();
+				captured.push((name.clone(), value.to_string()));
+				if match_tokens(rest, &text[end..], captured) {
+					return true;
+				}
+				captured.truncate(len);
+			}
+			false
+		}
+	}
+}
+
+fn is_numeric_field(name: &str) -> bool {
+	matches!(name, "track" | "disc")
+}
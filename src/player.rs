@@ -15,6 +15,12 @@ pub struct Player {
 	volume: f32,
 }
 
+impl Default for Player {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Player {
 	pub fn new() -> Self {
 		let stream = OutputStreamBuilder::open_default_stream().unwrap();
@@ -0,0 +1,681 @@
+use crate::load_album_and_track_lists::{self, Track};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const EXTENSIONS: &[&str] = &["m3u8", "m3u"];
+
+/// A named list of files, kept as an `.m3u8` file in the playlists folder.
+#[derive(Debug, Clone)]
+pub struct Playlist {
+	pub name: String,
+	pub path: PathBuf,
+	pub entries: Vec<Entry>,
+}
+
+/// A line of a playlist. The file may no longer exist or may be outside the
+/// library; `artist`, `title` and `length` come from the playlist, if it has them.
+#[derive(Debug, Clone)]
+pub struct Entry {
+	pub path: String,
+	pub artist: Option<String>,
+	pub title: Option<String>,
+	pub length: Option<u64>, // seconds
+}
+
+impl Entry {
+	pub fn from_track(track: &Track) -> Self {
+		Self {
+			path: track.path.clone(),
+			artist: Some(track.artist.clone()),
+			title: Some(track.track_name.clone()),
+			length: Some(track.length),
+		}
+	}
+
+	/// An entry from a playlist line and its "Artist - Title" description.
+	fn new(path: String, description: &str, length: Option<u64>) -> Self {
+		let (artist, title) = match description.split_once(" - ") {
+			Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+			None => (None, description.trim().to_string()),
+		};
+		Self {
+			path,
+			artist,
+			title: (!title.is_empty()).then_some(title),
+			length,
+		}
+	}
+
+	/// "Artist - Title", just the title, or `None` if the playlist has neither.
+	fn description(&self) -> Option<String> {
+		match (&self.artist, &self.title) {
+			(Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+			(None, Some(title)) => Some(title.clone()),
+			_ => None,
+		}
+	}
+
+	/// The artist and title, or the file name if there are none.
+	pub fn label(&self) -> String {
+		self.description().unwrap_or_else(|| {
+			Path::new(&self.path)
+				.file_name()
+				.map(|n| n.to_string_lossy().to_string())
+				.unwrap_or_else(|| self.path.clone())
+		})
+	}
+}
+
+/// The playlist file formats the queue can be saved as and loaded from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	M3u,
+	Pls,
+	Xspf,
+}
+
+impl Format {
+	pub const ALL: [Format; 3] = [Format::M3u, Format::Pls, Format::Xspf];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Format::M3u => "M3U8",
+			Format::Pls => "PLS",
+			Format::Xspf => "XSPF",
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Format::M3u => "m3u8",
+			Format::Pls => "pls",
+			Format::Xspf => "xspf",
+		}
+	}
+
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()?.to_lowercase().as_str() {
+			"m3u" | "m3u8" => Some(Format::M3u),
+			"pls" => Some(Format::Pls),
+			"xspf" => Some(Format::Xspf),
+			_ => None,
+		}
+	}
+
+	/// Guess the format of a file without a known extension from its contents.
+	fn sniff(text: &str) -> Self {
+		let start = text.trim_start_matches('\u{feff}').trim_start();
+		if start.to_lowercase().starts_with("[playlist]") {
+			Format::Pls
+		} else if start.starts_with("<?xml") || start.starts_with("<playlist") {
+			Format::Xspf
+		} else {
+			Format::M3u
+		}
+	}
+}
+
+/// The "playlists" folder inside the library folder.
+pub fn playlists_dir() -> PathBuf {
+	Path::new(&load_album_and_track_lists::library_dir()).join("playlists")
+}
+
+/// Every playlist in the playlists folder, sorted by name. Files that can't
+/// be read are left out.
+pub fn load_all() -> Vec<Playlist> {
+	let Ok(dir) = fs::read_dir(playlists_dir()) else {
+		return Vec::new();
+	};
+	let mut playlists: Vec<Playlist> = dir
+		.filter_map(|entry| entry.ok())
+		.map(|entry| entry.path())
+		.filter(|p| {
+			p.extension()
+				.and_then(|e| e.to_str())
+				.is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+		})
+		.filter_map(|p| Playlist::load(&p).ok())
+		.collect();
+	playlists.sort_by_key(|p| p.name.to_lowercase());
+	playlists
+}
+
+impl Playlist {
+	pub fn load(path: &Path) -> std::io::Result<Self> {
+		let bytes = fs::read(path)?;
+		let text = String::from_utf8_lossy(&bytes);
+		let base = path.parent().unwrap_or(Path::new("."));
+		Ok(Self {
+			name: path
+				.file_stem()
+				.map(|s| s.to_string_lossy().to_string())
+				.unwrap_or_default(),
+			path: path.to_path_buf(),
+			entries: parse_m3u(&text, base),
+		})
+	}
+
+	/// Create an empty playlist called `name`, failing if one already exists.
+	pub fn create(name: &str) -> Result<Self, String> {
+		let path = path_for(name)?;
+		if path.exists() {
+			return Err(format!("A playlist called \"{}\" already exists", name.trim()));
+		}
+		fs::create_dir_all(playlists_dir()).map_err(|e| e.to_string())?;
+		let playlist = Self {
+			name: name.trim().to_string(),
+			path,
+			entries: Vec::new(),
+		};
+		playlist.save().map_err(|e| e.to_string())?;
+		Ok(playlist)
+	}
+
+	pub fn save(&self) -> std::io::Result<()> {
+		fs::write(&self.path, to_m3u(&self.entries, None))
+	}
+
+	pub fn rename(&mut self, name: &str) -> Result<(), String> {
+		let path = path_for(name)?;
+		if path != self.path && path.exists() {
+			return Err(format!("A playlist called \"{}\" already exists", name.trim()));
+		}
+		fs::rename(&self.path, &path).map_err(|e| e.to_string())?;
+		self.name = name.trim().to_string();
+		self.path = path;
+		Ok(())
+	}
+
+	pub fn delete(&self) -> std::io::Result<()> {
+		fs::remove_file(&self.path)
+	}
+}
+
+/// Where a playlist called `name` is stored; slashes would make it a folder.
+fn path_for(name: &str) -> Result<PathBuf, String> {
+	let name = name.trim();
+	if name.is_empty() {
+		return Err(String::from("The playlist needs a name"));
+	}
+	let file_name: String = name
+		.chars()
+		.map(|c| if c == '/' || c == '\\' || c == '\0' { '_' } else { c })
+		.collect();
+	Ok(playlists_dir().join(format!("{}.m3u8", file_name)))
+}
+
+/// Read a playlist file in any of the supported formats. Relative paths are
+/// taken from the folder the file is in.
+pub fn read_file(path: &Path) -> std::io::Result<Vec<Entry>> {
+	let bytes = fs::read(path)?;
+	let text = String::from_utf8_lossy(&bytes);
+	let base = path.parent().unwrap_or(Path::new("."));
+	Ok(match Format::from_path(path).unwrap_or_else(|| Format::sniff(&text)) {
+		Format::M3u => parse_m3u(&text, base),
+		Format::Pls => parse_pls(&text, base),
+		Format::Xspf => parse_xspf(&text, base),
+	})
+}
+
+/// Write `entries` to `path`. With `relative_to`, paths inside that folder
+/// are written relative to it.
+pub fn write_file(
+	path: &Path,
+	entries: &[Entry],
+	format: Format,
+	relative_to: Option<&Path>,
+) -> std::io::Result<()> {
+	let text = match format {
+		Format::M3u => to_m3u(entries, relative_to),
+		Format::Pls => to_pls(entries, relative_to),
+		Format::Xspf => to_xspf(entries, relative_to),
+	};
+	fs::write(path, text)
+}
+
+/// Read M3U/M3U8 text. Relative paths are taken from `base`, the folder the
+/// playlist is in.
+pub fn parse_m3u(text: &str, base: &Path) -> Vec<Entry> {
+	let mut entries = Vec::new();
+	let mut info: Option<(Option<u64>, String)> = None;
+	for line in text.lines() {
+		let line = line.trim().trim_start_matches('\u{feff}');
+		if line.is_empty() {
+			continue;
+		}
+		if let Some(rest) = line.strip_prefix("#EXTINF:") {
+			// "#EXTINF:215,Artist - Title"; the length may be -1 for unknown
+			let (length, description) = rest.split_once(',').unwrap_or((rest, ""));
+			let length = length
+				.split_whitespace()
+				.next()
+				.and_then(parse_seconds);
+			info = Some((length, description.to_string()));
+			continue;
+		}
+		if line.starts_with('#') {
+			continue;
+		}
+		let (length, description) = info.take().unwrap_or((None, String::new()));
+		entries.push(Entry::new(resolve_path(line, base), &description, length));
+	}
+	entries
+}
+
+/// Read PLS text: numbered `FileN`, `TitleN` and `LengthN` keys under `[playlist]`.
+fn parse_pls(text: &str, base: &Path) -> Vec<Entry> {
+	let mut numbered: BTreeMap<u32, (Option<String>, String, Option<u64>)> = BTreeMap::new();
+	for line in text.lines() {
+		let Some((key, value)) = line.trim().split_once('=') else {
+			continue;
+		};
+		let key = key.trim().to_lowercase();
+		let value = value.trim();
+		let field = ["file", "title", "length"]
+			.into_iter()
+			.find_map(|name| Some((name, key.strip_prefix(name)?.parse::<u32>().ok()?)));
+		let Some((name, n)) = field else {
+			continue;
+		};
+		let entry = numbered.entry(n).or_default();
+		match name {
+			"file" => entry.0 = Some(resolve_path(value, base)),
+			"title" => entry.1 = value.to_string(),
+			_ => entry.2 = parse_seconds(value),
+		}
+	}
+	numbered
+		.into_values()
+		.filter_map(|(path, description, length)| Some(Entry::new(path?, &description, length)))
+		.collect()
+}
+
+/// Read XSPF: the `location`, `creator`, `title` and `duration` of every `track`.
+fn parse_xspf(text: &str, base: &Path) -> Vec<Entry> {
+	let mut entries = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find("<track>") {
+		rest = &rest[start + "<track>".len()..];
+		let end = rest.find("</track>").unwrap_or(rest.len());
+		let track = &rest[..end];
+		rest = &rest[end..];
+		let Some(location) = xml_element(track, "location") else {
+			continue;
+		};
+		let path = match location.strip_prefix("file://") {
+			Some(_) => resolve_path(&location, base),
+			None => resolve_path(&percent_decode(&location), base),
+		};
+		entries.push(Entry {
+			path,
+			artist: xml_element(track, "creator"),
+			title: xml_element(track, "title"),
+			length: xml_element(track, "duration")
+				.and_then(|ms| ms.parse::<u64>().ok())
+				.map(|ms| ms / 1000),
+		});
+	}
+	entries
+}
+
+/// The unescaped text of the first `<name>` element in `xml`.
+fn xml_element(xml: &str, name: &str) -> Option<String> {
+	let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+	let end = start + xml[start..].find(&format!("</{}>", name))?;
+	let text = xml_unescape(xml[start..end].trim());
+	(!text.is_empty()).then_some(text)
+}
+
+/// A length in seconds; negative lengths mean unknown.
+fn parse_seconds(s: &str) -> Option<u64> {
+	s.parse::<f64>().ok().filter(|l| *l >= 0.0).map(|l| l as u64)
+}
+
+/// A playlist line as an absolute path: `file://` URLs are decoded and
+/// relative paths joined to `base`, so they compare equal to library paths.
+pub fn resolve_path(line: &str, base: &Path) -> String {
+	let path = match line.strip_prefix("file://") {
+		Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)),
+		None => line.to_string(),
+	};
+	// "../Artist/Album/01.flac" from the playlists folder
+	let mut resolved = PathBuf::new();
+	for component in base.join(path).components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				resolved.pop();
+			}
+			other => resolved.push(other),
+		}
+	}
+	resolved.to_string_lossy().to_string()
+}
+
+fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%'
+			&& let Some(byte) = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())
+		{
+			out.push(byte);
+			i += 3;
+		} else {
+			out.push(bytes[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8_lossy(&out).to_string()
+}
+
+/// The path of `entry` as written to a playlist: relative to `relative_to`
+/// if it is inside that folder, otherwise absolute.
+fn entry_path(entry: &Entry, relative_to: Option<&Path>) -> String {
+	relative_to
+		.and_then(|dir| Path::new(&entry.path).strip_prefix(dir).ok())
+		.map(|p| p.to_string_lossy().to_string())
+		.unwrap_or_else(|| entry.path.clone())
+}
+
+/// Extended M3U. Playlists in the playlists folder use absolute paths, so the
+/// file keeps working if it is moved.
+pub fn to_m3u(entries: &[Entry], relative_to: Option<&Path>) -> String {
+	let mut out = String::from("#EXTM3U\n");
+	for entry in entries {
+		let description = entry.description();
+		if description.is_some() || entry.length.is_some() {
+			out.push_str(&format!(
+				"#EXTINF:{},{}\n",
+				entry.length.map(|l| l as i64).unwrap_or(-1),
+				description.unwrap_or_default()
+			));
+		}
+		out.push_str(&entry_path(entry, relative_to));
+		out.push('\n');
+	}
+	out
+}
+
+fn to_pls(entries: &[Entry], relative_to: Option<&Path>) -> String {
+	let mut out = String::from("[playlist]\n");
+	for (i, entry) in entries.iter().enumerate() {
+		let n = i + 1;
+		out.push_str(&format!("File{}={}\n", n, entry_path(entry, relative_to)));
+		if let Some(description) = entry.description() {
+			out.push_str(&format!("Title{}={}\n", n, description));
+		}
+		out.push_str(&format!(
+			"Length{}={}\n",
+			n,
+			entry.length.map(|l| l as i64).unwrap_or(-1)
+		));
+	}
+	out.push_str(&format!("NumberOfEntries={}\nVersion=2\n", entries.len()));
+	out
+}
+
+fn to_xspf(entries: &[Entry], relative_to: Option<&Path>) -> String {
+	let mut out = String::from(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n\t<trackList>\n",
+	);
+	for entry in entries {
+		let path = entry_path(entry, relative_to);
+		// absolute paths become file:// URLs, relative ones relative URLs
+		let location = if Path::new(&path).is_absolute() {
+			format!("file://{}", percent_encode(&path))
+		} else {
+			percent_encode(&path)
+		};
+		out.push_str("\t\t<track>\n");
+		out.push_str(&format!("\t\t\t<location>{}</location>\n", xml_escape(&location)));
+		if let Some(artist) = &entry.artist {
+			out.push_str(&format!("\t\t\t<creator>{}</creator>\n", xml_escape(artist)));
+		}
+		if let Some(title) = &entry.title {
+			out.push_str(&format!("\t\t\t<title>{}</title>\n", xml_escape(title)));
+		}
+		if let Some(length) = entry.length {
+			out.push_str(&format!("\t\t\t<duration>{}</duration>\n", length * 1000));
+		}
+		out.push_str("\t\t</track>\n");
+	}
+	out.push_str("\t</trackList>\n</playlist>\n");
+	out
+}
+
+/// Percent-encode everything but unreserved characters and slashes.
+fn percent_encode(s: &str) -> String {
+	let mut out = String::with_capacity(s.len());
+	for byte in s.bytes() {
+		if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+			out.push(byte as char);
+		} else {
+			out.push_str(&format!("%{:02X}", byte));
+		}
+	}
+	out
+}
+
+fn xml_escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+fn xml_unescape(s: &str) -> String {
+	s.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+/// The library track each entry points at. Entries are looked up by path,
+/// then relative to `library_dir` (for playlists saved with library-relative
+/// paths somewhere else), then by artist and title for files that have moved.
+pub fn find_tracks<'a>(
+	entries: &[Entry],
+	tracks: &'a [Track],
+	base: &Path,
+	library_dir: &Path,
+) -> Vec<Option<&'a Track>> {
+	let by_path: HashMap<&str, &Track> = tracks.iter().map(|t| (t.path.as_str(), t)).collect();
+	let mut by_name: HashMap<String, &Track> = HashMap::new();
+	for track in tracks {
+		by_name
+			.entry(format!("{} - {}", track.artist, track.track_name).to_lowercase())
+			.or_insert(track);
+	}
+	entries
+		.iter()
+		.map(|entry| {
+			by_path
+				.get(entry.path.as_str())
+				.or_else(|| {
+					let relative = Path::new(&entry.path).strip_prefix(base).ok()?;
+					by_path.get(library_dir.join(relative).to_string_lossy().as_ref())
+				})
+				.or_else(|| by_name.get(&entry.description()?.to_lowercase()))
+				.copied()
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(path: &str, artist: Option<&str>, title: Option<&str>, length: Option<u64>) -> Entry {
+		Entry {
+			path: path.to_string(),
+			artist: artist.map(String::from),
+			title: title.map(String::from),
+			length,
+		}
+	}
+
+	fn track(path: &str, artist: &str, title: &str) -> Track {
+		let mut track = Track::new(path.to_string());
+		track.artist = artist.to_string();
+		track.track_name = title.to_string();
+		track
+	}
+
+	fn paths(entries: &[Entry]) -> Vec<&str> {
+		entries.iter().map(|e| e.path.as_str()).collect()
+	}
+
+	fn sample() -> Vec<Entry> {
+		vec![
+			entry("/music/Abba/Gold/01 Waterloo.flac", Some("Abba"), Some("Waterloo"), Some(168)),
+			entry("/music/Zed/First Album/02 Two.mp3", None, Some("Two"), None),
+			entry("/elsewhere/café & co/<3>.ogg", None, None, None),
+		]
+	}
+
+	/// Write `entries` in `format` and read them back from a file in `dir`.
+	fn round_trip(entries: &[Entry], format: Format, dir: &Path, relative_to: Option<&Path>) -> Vec<Entry> {
+		let text = match format {
+			Format::M3u => to_m3u(entries, relative_to),
+			Format::Pls => to_pls(entries, relative_to),
+			Format::Xspf => to_xspf(entries, relative_to),
+		};
+		match format {
+			Format::M3u => parse_m3u(&text, dir),
+			Format::Pls => parse_pls(&text, dir),
+			Format::Xspf => parse_xspf(&text, dir),
+		}
+	}
+
+	#[test]
+	fn absolute_paths_round_trip_in_every_format() {
+		let entries = sample();
+		for format in Format::ALL {
+			let read = round_trip(&entries, format, Path::new("/somewhere/else"), None);
+			assert_eq!(paths(&read), paths(&entries), "{}", format.name());
+			assert_eq!(read[0].artist.as_deref(), Some("Abba"), "{}", format.name());
+			assert_eq!(read[0].title.as_deref(), Some("Waterloo"), "{}", format.name());
+			assert_eq!(read[0].length, Some(168), "{}", format.name());
+			assert_eq!(read[1].artist, None, "{}", format.name());
+			assert_eq!(read[1].title.as_deref(), Some("Two"), "{}", format.name());
+			assert_eq!(read[2].label(), "<3>.ogg", "{}", format.name());
+		}
+	}
+
+	#[test]
+	fn relative_paths_are_only_used_inside_the_folder() {
+		let entries = sample();
+		let library = Path::new("/music");
+		let text = to_m3u(&entries, Some(library));
+		assert!(text.contains("\nAbba/Gold/01 Waterloo.flac\n"));
+		assert!(text.contains("\n/elsewhere/café & co/<3>.ogg\n"));
+		for format in Format::ALL {
+			// read back from a playlist saved in the library folder
+			let read = round_trip(&entries, format, library, Some(library));
+			assert_eq!(paths(&read), paths(&entries), "{}", format.name());
+		}
+	}
+
+	#[test]
+	fn relative_paths_are_resolved_from_the_playlist_folder() {
+		let base = Path::new("/music/playlists");
+		assert_eq!(resolve_path("../Abba/Gold/01.flac", base), "/music/Abba/Gold/01.flac");
+		assert_eq!(resolve_path("./02.flac", base), "/music/playlists/02.flac");
+		assert_eq!(resolve_path("/abs/03.flac", base), "/abs/03.flac");
+	}
+
+	#[test]
+	fn file_urls_are_percent_decoded() {
+		let base = Path::new("/music");
+		assert_eq!(
+			resolve_path("file:///music/Caf%C3%A9%20Tacvba/01%20Trouble.flac", base),
+			"/music/Café Tacvba/01 Trouble.flac"
+		);
+		assert_eq!(resolve_path("file://localhost/a%2Fb/c.mp3", base), "/a/b/c.mp3");
+		// a lone or broken escape is kept as it is
+		assert_eq!(resolve_path("file:///100%/50%zz.mp3", base), "/100%/50%zz.mp3");
+		// plain paths aren't URLs, so a % in them is a real %
+		assert_eq!(resolve_path("/music/100%20.mp3", base), "/music/100%20.mp3");
+
+		let xspf = "<playlist><trackList><track><location>file:///music/A%20B/c%26d.flac</location></track>\
+			<track><location>Sub%20Dir/e.flac</location></track></trackList></playlist>";
+		assert_eq!(
+			paths(&parse_xspf(xspf, base)),
+			["/music/A B/c&d.flac", "/music/Sub Dir/e.flac"]
+		);
+	}
+
+	#[test]
+	fn pls_entries_follow_their_numbers() {
+		let text = "[playlist]\n\
+			File2=/music/b.mp3\n\
+			Title2=Zed - Two\n\
+			file1=/music/a.mp3\n\
+			Length1=-1\n\
+			Length2=200\n\
+			Title3=Has no file\n\
+			File10=/music/j.mp3\n\
+			NumberOfEntries=4\n\
+			Version=2\n";
+		let entries = parse_pls(text, Path::new("/"));
+		assert_eq!(paths(&entries), ["/music/a.mp3", "/music/b.mp3", "/music/j.mp3"]);
+		assert_eq!(entries[0].length, None);
+		assert_eq!(entries[1].length, Some(200));
+		assert_eq!(entries[1].artist.as_deref(), Some("Zed"));
+		assert_eq!(entries[1].title.as_deref(), Some("Two"));
+
+		let written = to_pls(&sample(), None);
+		assert!(written.contains("File1=/music/Abba/Gold/01 Waterloo.flac\nTitle1=Abba - Waterloo\nLength1=168\n"));
+		assert!(written.contains("File3=/elsewhere/café & co/<3>.ogg\nLength3=-1\n"));
+		assert!(written.ends_with("NumberOfEntries=3\nVersion=2\n"));
+	}
+
+	#[test]
+	fn descriptions_split_into_artist_and_title() {
+		let text = "#EXTM3U\n\
+			#EXTINF:100,Artist - Title - Part 2\n/a.mp3\n\
+			#EXTINF:-1,Just a title\n/b.mp3\n\
+			#EXTINF:5,\n/c/d.mp3\n\
+			/e.mp3\n";
+		let entries = parse_m3u(text, Path::new("/"));
+		assert_eq!(entries[0].artist.as_deref(), Some("Artist"));
+		assert_eq!(entries[0].title.as_deref(), Some("Title - Part 2"));
+		assert_eq!(entries[1].artist, None);
+		assert_eq!(entries[1].title.as_deref(), Some("Just a title"));
+		assert_eq!(entries[1].length, None);
+		assert_eq!(entries[2].label(), "d.mp3");
+		assert_eq!(entries[2].length, Some(5));
+		assert_eq!(entries[3].label(), "e.mp3");
+	}
+
+	#[test]
+	fn moved_files_are_found_by_artist_and_title() {
+		let tracks = vec![
+			track("/music/Abba/Gold/01 Waterloo.flac", "Abba", "Waterloo"),
+			track("/music/Zed/First Album/02 Two.mp3", "Zed", "Two"),
+		];
+		let entries = vec![
+			// moved since the playlist was saved
+			entry("/old/waterloo.flac", Some("ABBA"), Some("waterloo"), None),
+			// saved relative to the library somewhere else
+			entry("/portable/Zed/First Album/02 Two.mp3", None, None, None),
+			// no artist, so the title alone isn't enough
+			entry("/old/two.mp3", None, Some("Two"), None),
+		];
+		let found = find_tracks(&entries, &tracks, Path::new("/portable"), Path::new("/music"));
+		let found: Vec<Option<&str>> = found.iter().map(|t| t.map(|t| t.path.as_str())).collect();
+		assert_eq!(
+			found,
+			[
+				Some("/music/Abba/Gold/01 Waterloo.flac"),
+				Some("/music/Zed/First Album/02 Two.mp3"),
+				None,
+			]
+		);
+	}
+}
@@ -0,0 +1,646 @@
+use crate::app::{self, App, Popup, PromptKind};
+use crate::history;
+use crate::load_album_and_track_lists;
+use crate::stats;
+use ratatui::{
+	Frame,
+	layout::{Alignment, Constraint, Direction, Flex, Layout, Position, Rect},
+	style::{Color, Modifier, Style},
+	text::{Line, Span},
+	widgets::{
+		Bar, BarChart, BarGroup, Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Table,
+	},
+};
+
+pub fn draw(f: &mut Frame, app: &mut App, popup: Popup) {
+	let area = match popup {
+		Popup::TagEditor => centered_rect(f.area(), 60, 60),
+		Popup::Prompt(_) | Popup::DeletePlaylist => {
+			let [area] = Layout::vertical([Constraint::Length(3)])
+				.flex(Flex::Center)
+				.areas(centered_rect(f.area(), 60, 100));
+			area
+		}
+		Popup::Stats => centered_rect(f.area(), 90, 90),
+		Popup::Details => centered_rect(f.area(), 60, 70),
+		Popup::ExportQueue => centered_rect(f.area(), 50, 40),
+		_ => centered_rect(f.area(), 80, 70),
+	};
+	f.render_widget(Clear, area);
+	match popup {
+		Popup::LibraryIssues => draw_library_issues(f, app, area),
+		Popup::Duplicates => draw_duplicates(f, app, area),
+		Popup::TagEditor => draw_tag_editor(f, app, area),
+		Popup::BulkEditConfirm => draw_bulk_edit_confirm(f, app, area),
+		Popup::Prompt(kind) => draw_prompt(f, app, area, kind),
+		Popup::OrganizePreview => draw_organize_preview(f, app, area),
+		Popup::Filters => draw_filters(f, app, area),
+		Popup::Stats => draw_stats(f, app, area),
+		Popup::Details => draw_details(f, app, area),
+		Popup::AddToPlaylist => draw_add_to_playlist(f, app, area),
+		Popup::DeletePlaylist => draw_delete_playlist(f, app, area),
+		Popup::ExportQueue => draw_export_queue(f, app, area),
+		Popup::History => draw_history(f, app, area),
+	}
+}
+
+/// A rectangle of `percent_x` by `percent_y` centered in `area`.
+pub fn centered_rect(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+	let [area] = Layout::vertical([Constraint::Percentage(percent_y)])
+		.flex(Flex::Center)
+		.areas(area);
+	let [area] = Layout::horizontal([Constraint::Percentage(percent_x)])
+		.flex(Flex::Center)
+		.areas(area);
+	area
+}
+
+fn popup_block(app: &App, title: String) -> Block<'static> {
+	Block::default()
+		.title(title)
+		.title_alignment(Alignment::Center)
+		.borders(Borders::ALL)
+		.border_type(BorderType::Rounded)
+		.border_style(Style::default().fg(app.highlight_color))
+}
+
+fn highlight_style(app: &App) -> Style {
+	Style::default()
+		.fg(Color::Black)
+		.add_modifier(Modifier::BOLD)
+		.bg(app.highlight_color)
+}
+
+fn draw_library_issues(f: &mut Frame, app: &mut App, area: Rect) {
+	let skipped = app
+		.scan_issues
+		.iter()
+		.filter(|i| i.reason.is_skipped())
+		.count();
+	let title = format!(
+		" Library issues ({} skipped, {} warnings)",
+		skipped,
+		app.scan_issues.len() - skipped
+	);
+
+	let rows: Vec<Row> = if app.scan_issues.is_empty() {
+		vec![Row::new(vec![Cell::from("No problems found"), Cell::from("")])]
+	} else {
+		app.scan_issues
+			.iter()
+			.map(|issue| {
+				let style = if issue.reason.is_skipped() {
+					Style::default().fg(Color::Red)
+				} else {
+					Style::default().fg(Color::Yellow)
+				};
+				Row::new(vec![
+					Cell::from(issue.reason.to_string()).style(style),
+					Cell::from(issue.path.clone()),
+				])
+			})
+			.collect()
+	};
+
+	let table = Table::new(rows, [Constraint::Percentage(35), Constraint::Fill(1)])
+		.block(popup_block(app, title))
+		.row_highlight_style(highlight_style(app))
+		.highlight_symbol("  ")
+		.column_spacing(2);
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_history(f: &mut Frame, app: &mut App, area: Rect) {
+	let title = format!(
+		" History ({} plays) │ [a] queue track  [A] queue album",
+		app.history.plays.len()
+	);
+	let now = history::now();
+	let dim = Style::default().fg(Color::DarkGray);
+	let rows: Vec<Row> = if app.history.plays.is_empty() {
+		vec![Row::new(vec![Cell::from("Nothing played yet")])]
+	} else {
+		app.history
+			.plays
+			.iter()
+			.rev()
+			.map(|play| {
+				Row::new(vec![
+					Cell::from(history::format_ago(play.started, now)).style(dim),
+					Cell::from(play.artist.clone()),
+					Cell::from(play.title.clone()),
+					Cell::from(play.album.clone()),
+					Cell::from(format!("{:02}:{:02}", play.listened / 60, play.listened % 60)),
+					Cell::from(if play.skipped { "skipped" } else { "" }).style(dim),
+				])
+			})
+			.collect()
+	};
+	let table = Table::new(
+		rows,
+		[
+			Constraint::Length(10),
+			Constraint::Fill(1),
+			Constraint::Fill(2),
+			Constraint::Fill(1),
+			Constraint::Length(5),
+			Constraint::Length(7),
+		],
+	)
+	.block(popup_block(app, title))
+	.row_highlight_style(highlight_style(app))
+	.highlight_symbol("  ")
+	.column_spacing(2);
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_filters(f: &mut Frame, app: &mut App, area: Rect) {
+	let rows: Vec<Row> = app
+		.filter_rows
+		.iter()
+		.map(|row| Row::new(vec![Cell::from(row.label.clone())]))
+		.collect();
+	let table = Table::new(rows, [Constraint::Fill(1)])
+		.block(popup_block(app, String::from(" Quick filters │ [Enter] apply")))
+		.row_highlight_style(highlight_style(app))
+		.highlight_symbol("  ");
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_add_to_playlist(f: &mut Frame, app: &mut App, area: Rect) {
+	let mut rows = vec![Row::new(vec![Cell::from("New playlist…")])
+		.style(Style::default().add_modifier(Modifier::ITALIC))];
+	rows.extend(app.playlists.iter().map(|playlist| {
+		Row::new(vec![Cell::from(format!(
+			"{} ({})",
+			playlist.name,
+			playlist.entries.len()
+		))])
+	}));
+	let title = format!(
+		" Add {} to playlist │ [Enter] add",
+		if app.playlist_pending.len() == 1 {
+			String::from("1 track")
+		} else {
+			format!("{} tracks", app.playlist_pending.len())
+		}
+	);
+	let table = Table::new(rows, [Constraint::Fill(1)])
+		.block(popup_block(app, title))
+		.row_highlight_style(highlight_style(app))
+		.highlight_symbol("  ");
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_delete_playlist(f: &mut Frame, app: &mut App, area: Rect) {
+	let name = app.selected_playlist_name().unwrap_or_default().to_string();
+	let text = Paragraph::new(format!("Delete \"{}\"?", name))
+		.alignment(Alignment::Center)
+		.block(popup_block(app, String::from(" Delete playlist │ [Enter] delete")));
+	f.render_widget(text, area);
+}
+
+fn draw_export_queue(f: &mut Frame, app: &mut App, area: Rect) {
+	let rows: Vec<Row> = app::export_choices()
+		.into_iter()
+		.map(|(format, relative)| {
+			Row::new(vec![
+				Cell::from(format.name()),
+				Cell::from(if relative {
+					"paths relative to the library folder"
+				} else {
+					"absolute paths"
+				}),
+			])
+		})
+		.collect();
+	let title = format!(" Save {} tracks as │ [Enter] choose", app.queue.len());
+	let table = Table::new(rows, [Constraint::Length(6), Constraint::Fill(1)])
+		.block(popup_block(app, title))
+		.row_highlight_style(highlight_style(app))
+		.highlight_symbol("  ");
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_details(f: &mut Frame, app: &mut App, area: Rect) {
+	let Some(track) = &app.details else {
+		return;
+	};
+	let from_path = |value: String, inferred: bool| {
+		if inferred { format!("{} (from path)", value) } else { value }
+	};
+	let number = |n: i32, total: i32| match (n, total) {
+		(0, _) => String::new(),
+		(n, 0) => n.to_string(),
+		(n, total) => format!("{}/{}", n, total),
+	};
+	let audio = &track.audio;
+	let quality = if audio.is_hires() {
+		"Hi-res lossless"
+	} else if audio.is_lossless() {
+		"Lossless"
+	} else {
+		"Lossy"
+	};
+	let fields: Vec<(&str, String)> = vec![
+		("Title", from_path(track.track_name.clone(), track.inferred.title)),
+		("Artist", from_path(track.artist.clone(), track.inferred.artist)),
+		(
+			"Album artist",
+			from_path(track.album_artist.clone(), track.inferred.album_artist),
+		),
+		("Album", from_path(track.album.clone(), track.inferred.album)),
+		(
+			"Track",
+			from_path(number(track.track_num, track.track_total), track.inferred.track_num),
+		),
+		(
+			"Disc",
+			from_path(number(track.disc_num, track.disc_total), track.inferred.disc_num),
+		),
+		("Year", if track.year > 0 { track.year.to_string() } else { String::new() }),
+		("Genre", track.genre_label()),
+		("Release type", track.release_type.clone()),
+		("Length", format!("{:02}:{:02}", track.length / 60, track.length % 60)),
+		("", String::new()),
+		("Format", audio.format.clone()),
+		("Quality", String::from(quality)),
+		(
+			"Bit depth",
+			if audio.bit_depth > 0 { format!("{}-bit", audio.bit_depth) } else { String::new() },
+		),
+		("Sample rate", load_album_and_track_lists::sample_rate_label(audio.sample_rate)),
+		("Channels", audio.channels_label()),
+		("Bitrate", if audio.bitrate > 0 { format!("{} kbps", audio.bitrate) } else { String::new() }),
+		("", String::new()),
+		("Path", track.path.clone()),
+	];
+	let rows: Vec<Row> = fields
+		.into_iter()
+		.map(|(name, value)| {
+			Row::new(vec![
+				Cell::from(name).style(Style::default().add_modifier(Modifier::BOLD)),
+				Cell::from(value),
+			])
+		})
+		.collect();
+	let table = Table::new(rows, [Constraint::Length(14), Constraint::Fill(1)])
+		.block(popup_block(app, String::from(" Track details")));
+	f.render_widget(table, area);
+}
+
+fn draw_stats(f: &mut Frame, app: &mut App, area: Rect) {
+	let block = popup_block(app, String::from(" Library statistics"));
+	let inner = block.inner(area);
+	f.render_widget(block, area);
+	let stats = &app.stats;
+	let [summary, top, bottom] = Layout::vertical([
+		Constraint::Length(2),
+		Constraint::Fill(1),
+		Constraint::Fill(1),
+	])
+	.areas(inner);
+	let [formats_area, rates_area, missing_area] = Layout::horizontal([
+		Constraint::Fill(1),
+		Constraint::Fill(1),
+		Constraint::Fill(1),
+	])
+	.areas(top);
+	let [artists_area, albums_area] =
+		Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(bottom);
+
+	f.render_widget(
+		Paragraph::new(format!(
+			"{} tracks │ {} albums │ {} artists │ {} of music",
+			stats.tracks,
+			stats.albums,
+			stats.artists,
+			stats::format_playtime(stats.playtime)
+		))
+		.alignment(Alignment::Center),
+		summary,
+	);
+
+	let color = app.highlight_color;
+	let chart_block = |title: &str| {
+		Block::default()
+			.title(format!(" {} ", title))
+			.title_alignment(Alignment::Center)
+			.borders(Borders::ALL)
+			.border_type(BorderType::Rounded)
+	};
+	// columns as wide as their longest label, as long as they all fit in `area`
+	let column_chart = |title: &str, bars: Vec<Bar<'static>>, labels: Vec<String>, area: Rect| {
+		let longest = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0).max(3);
+		let fits = (area.width.saturating_sub(1) as usize / labels.len().max(1)).saturating_sub(1);
+		let width = longest.min(fits).max(1);
+		BarChart::default()
+			.block(chart_block(title))
+			.bar_width(width as u16)
+			.bar_gap(1)
+			.bar_style(Style::default().fg(color))
+			.value_style(Style::default().fg(Color::Black).bg(color))
+			.data(BarGroup::default().bars(&bars))
+	};
+	let row_chart = |title: &str, bars: Vec<Bar<'static>>| {
+		BarChart::default()
+			.block(chart_block(title))
+			.direction(Direction::Horizontal)
+			.bar_width(1)
+			.bar_gap(0)
+			.bar_style(Style::default().fg(color))
+			.value_style(Style::default().fg(Color::Black).bg(color))
+			.data(BarGroup::default().bars(&bars))
+	};
+	let bar = |label: String, value: u64, text: String| {
+		Bar::default()
+			.label(Line::from(label))
+			.value(value)
+			.text_value(text)
+	};
+
+	let counts = |title: &str, counts: Vec<(String, u64)>, area: Rect| {
+		let labels: Vec<String> = counts.iter().map(|(label, _)| label.clone()).collect();
+		let bars = counts
+			.into_iter()
+			.map(|(label, n)| bar(label, n, n.to_string()))
+			.collect();
+		column_chart(title, bars, labels, area)
+	};
+	f.render_widget(counts("Formats", stats.formats.clone(), formats_area), formats_area);
+	f.render_widget(
+		counts(
+			"Sample rates",
+			stats
+				.sample_rates
+				.iter()
+				.map(|(hz, n)| (load_album_and_track_lists::sample_rate_label(*hz), *n))
+				.collect(),
+			rates_area,
+		),
+		rates_area,
+	);
+	f.render_widget(
+		counts(
+			"Tracks missing tags",
+			stats
+				.missing_tags
+				.iter()
+				.map(|(tag, n)| (tag.to_string(), *n))
+				.collect(),
+			missing_area,
+		),
+		missing_area,
+	);
+
+	// labels are cut to leave the bars some room
+	let label_width = (artists_area.width as usize / 3).max(8);
+	let cut = |label: &str| -> String {
+		if label.chars().count() > label_width {
+			let mut cut: String = label.chars().take(label_width - 1).collect();
+			cut.push('…');
+			cut
+		} else {
+			label.to_string()
+		}
+	};
+	let artists = stats
+		.artist_playtime
+		.iter()
+		.map(|(artist, secs)| bar(cut(artist), *secs, stats::format_playtime(*secs)))
+		.collect();
+	f.render_widget(row_chart("Playtime per artist", artists), artists_area);
+
+	let albums = stats
+		.largest_albums
+		.iter()
+		.map(|(album, n)| {
+			let text = if *n == 1 { String::from("1 track") } else { format!("{} tracks", n) };
+			bar(cut(album), *n, text)
+		})
+		.collect();
+	f.render_widget(row_chart("Largest albums", albums), albums_area);
+}
+
+fn draw_duplicates(f: &mut Frame, app: &mut App, area: Rect) {
+	let title = format!(
+		" Duplicates ({} groups) │ [v] verify audio",
+		app.duplicates.len()
+	);
+
+	let rows: Vec<Row> = if app.duplicates.is_empty() {
+		vec![Row::new(vec![Cell::from("No duplicates found")])]
+	} else {
+		app.duplicate_rows()
+			.into_iter()
+			.map(|(g, copy)| {
+				let group = &app.duplicates[g];
+				let verifying = app.is_verifying(group);
+				match copy {
+					None => Row::new(vec![Cell::from(format!(
+						"{} - {} ({} copies)",
+						group.artist,
+						group.title,
+						group.copies.len()
+					))])
+					.style(Style::default().add_modifier(Modifier::BOLD)),
+					Some(c) => {
+						let track = &group.copies[c];
+						let verified = match group.copy_matches(c) {
+							_ if verifying => Cell::from("checking…")
+								.style(Style::default().fg(Color::DarkGray)),
+							Some(true) => Cell::from("identical")
+								.style(Style::default().fg(Color::Green)),
+							Some(false) => Cell::from("differs")
+								.style(Style::default().fg(Color::Yellow)),
+							None if group.hashes.is_some() => Cell::from("unreadable")
+								.style(Style::default().fg(Color::Red)),
+							None => Cell::from(""),
+						};
+						Row::new(vec![
+							Cell::from(format!("  {}", track.path)),
+							Cell::from(track.audio.format.clone()),
+							Cell::from(format!("{} kbps", track.audio.bitrate)),
+							Cell::from(format!(
+								"{:02}:{:02}",
+								track.length / 60,
+								track.length % 60
+							)),
+							verified,
+						])
+					}
+				}
+			})
+			.collect()
+	};
+
+	let table = Table::new(
+		rows,
+		[
+			Constraint::Fill(1),    // title / path
+			Constraint::Length(6),  // format
+			Constraint::Length(10), // bitrate
+			Constraint::Length(6),  // length
+			Constraint::Length(10), // verified
+		],
+	)
+	.block(popup_block(app, title))
+	.row_highlight_style(highlight_style(app))
+	.highlight_symbol("  ")
+	.column_spacing(2);
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_tag_editor(f: &mut Frame, app: &mut App, area: Rect) {
+	let Some(editor) = &app.tag_editor else {
+		return;
+	};
+	let title = if editor.paths.len() == 1 {
+		String::from(" Edit tags")
+	} else {
+		format!(" Edit tags ({} tracks)", editor.paths.len())
+	};
+	let block = popup_block(app, title);
+	let inner = block.inner(area);
+	f.render_widget(block, area);
+
+	const LABEL_WIDTH: u16 = 14;
+	let dim = Style::default().fg(Color::DarkGray);
+	let mut lines: Vec<Line> = editor
+		.fields
+		.iter()
+		.enumerate()
+		.map(|(i, input)| {
+			let label = Span::styled(
+				format!("{:>width$}  ", input.field.label(), width = LABEL_WIDTH as usize - 2),
+				if i == editor.selected {
+					Style::default().fg(app.highlight_color).add_modifier(Modifier::BOLD)
+				} else {
+					Style::default()
+				},
+			);
+			let value = if !input.editable {
+				Span::styled("(per track)", dim)
+			} else if input.value.is_empty() && input.original.is_none() {
+				Span::styled("(various, unchanged)", dim)
+			} else if input.is_changed() {
+				Span::styled(input.value.clone(), Style::default().add_modifier(Modifier::BOLD))
+			} else {
+				Span::raw(input.value.clone())
+			};
+			Line::from(vec![label, value])
+		})
+		.collect();
+	lines.push(Line::raw(""));
+	match &editor.error {
+		Some(err) => lines.push(Line::styled(err.clone(), Style::default().fg(Color::Red))),
+		None => lines.push(Line::styled(
+			"[Enter] save  [Tab]/[Up]/[Down] switch field  [Esc] cancel",
+			dim,
+		)),
+	}
+	f.render_widget(Paragraph::new(lines), inner);
+
+	// cursor in the selected field; placeholders keep it at the start
+	let input = &editor.fields[editor.selected];
+	if input.editable {
+		let offset = if input.value.is_empty() { 0 } else { editor.cursor };
+		#[allow(clippy::cast_possible_truncation)]
+		f.set_cursor_position(Position::new(
+			inner.x + LABEL_WIDTH + offset as u16,
+			inner.y + editor.selected as u16,
+		));
+	}
+}
+
+fn draw_bulk_edit_confirm(f: &mut Frame, app: &mut App, area: Rect) {
+	let files = crate::bulk_edit::group_by_path(&app.bulk_changes).len();
+	let title = format!(
+		" Write {} changes to {} files? │ [Enter] write  [Esc] cancel",
+		app.bulk_changes.len(),
+		files
+	);
+	let rows: Vec<Row> = app
+		.bulk_changes
+		.iter()
+		.map(|change| {
+			Row::new(vec![
+				Cell::from(change.path.clone()),
+				Cell::from(change.field.label()),
+				Cell::from(change.old.clone()).style(Style::default().fg(Color::DarkGray)),
+				Cell::from(change.new.clone()).style(Style::default().add_modifier(Modifier::BOLD)),
+			])
+		})
+		.collect();
+	let table = Table::new(
+		rows,
+		[
+			Constraint::Percentage(40), // path
+			Constraint::Length(12),     // field
+			Constraint::Percentage(30), // old
+			Constraint::Fill(1),        // new
+		],
+	)
+	.header(Row::new(vec!["File", "Field", "Old", "New"]).style(Style::default().fg(app.highlight_color)))
+	.block(popup_block(app, title))
+	.row_highlight_style(highlight_style(app))
+	.highlight_symbol("  ")
+	.column_spacing(2);
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
+
+fn draw_prompt(f: &mut Frame, app: &mut App, area: Rect, kind: PromptKind) {
+	let block = popup_block(app, kind.title().to_string());
+	let inner = block.inner(area);
+	let input = Paragraph::new(app.prompt.value.as_str()).block(block);
+	f.render_widget(input, area);
+	#[allow(clippy::cast_possible_truncation)]
+	f.set_cursor_position(Position::new(inner.x + app.prompt.cursor as u16, inner.y));
+}
+
+fn draw_organize_preview(f: &mut Frame, app: &mut App, area: Rect) {
+	let collisions = app
+		.organize_plan
+		.iter()
+		.filter(|m| m.collision.is_some())
+		.count();
+	let title = format!(
+		" Move {} files ({} collisions skipped)? │ [Enter] move  [Esc] cancel",
+		app.organize_plan.len() - collisions,
+		collisions
+	);
+	let dim = Style::default().fg(Color::DarkGray);
+	let rows: Vec<Row> = app
+		.organize_plan
+		.iter()
+		.map(|m| {
+			let mut lines = vec![
+				Line::raw(m.from.display().to_string()),
+				Line::raw(format!("→ {}", m.to.display())),
+			];
+			lines.extend(m.sidecars.iter().map(|(from, to)| {
+				Line::styled(
+					format!(
+						"  + {} → {}",
+						from.file_name().unwrap_or_default().to_string_lossy(),
+						to.display()
+					),
+					dim,
+				)
+			}));
+			if let Some(collision) = &m.collision {
+				lines.push(Line::styled(
+					format!("  ! {}", collision),
+					Style::default().fg(Color::Red),
+				));
+			}
+			let height = lines.len() as u16;
+			Row::new(vec![Cell::from(lines)]).height(height)
+		})
+		.collect();
+	let table = Table::new(rows, [Constraint::Fill(1)])
+		.block(popup_block(app, title))
+		.row_highlight_style(highlight_style(app))
+		.highlight_symbol("  ");
+	f.render_stateful_widget(table, area, &mut app.popup_state);
+}
@@ -0,0 +1,50 @@
+/// Single-line text input used by popups that ask for a name or a path.
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+	pub value: String,
+	pub cursor: usize, // char index
+}
+
+impl TextInput {
+	pub fn new(value: &str) -> Self {
+		Self {
+			value: value.to_string(),
+			cursor: value.chars().count(),
+		}
+	}
+
+	fn byte_index(&self) -> usize {
+		self.value
+			.char_indices()
+			.map(|(i, _)| i)
+			.nth(self.cursor)
+			.unwrap_or(self.value.len())
+	}
+
+	pub fn enter_char(&mut self, c: char) {
+		let index = self.byte_index();
+		self.value.insert(index, c);
+		self.cursor += 1;
+	}
+
+	pub fn delete_char(&mut self) {
+		if self.cursor == 0 {
+			return;
+		}
+		self.value = self
+			.value
+			.chars()
+			.take(self.cursor - 1)
+			.chain(self.value.chars().skip(self.cursor))
+			.collect();
+		self.cursor -= 1;
+	}
+
+	pub fn move_cursor_left(&mut self) {
+		self.cursor = self.cursor.saturating_sub(1);
+	}
+
+	pub fn move_cursor_right(&mut self) {
+		self.cursor = (self.cursor + 1).min(self.value.chars().count());
+	}
+}
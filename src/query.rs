@@ -0,0 +1,326 @@
+use crate::history;
+use crate::load_album_and_track_lists::{Album, Track};
+
+/// A find term split into plain text and `key:value` filters, e.g.
+/// `genre:jazz year:1990s is:lossless blue`. Values with spaces can be
+/// quoted: `genre:"hip hop"`.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+	text: String,
+	filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone)]
+enum Filter {
+	Artist(String),
+	Album(String),
+	Title(String),
+	Genre(String),
+	Year(i64, i64), // inclusive ranges
+	ReleaseType(String),
+	Format(String),
+	BitDepth(i64, i64),
+	SampleRate(i64, i64), // Hz
+	Channels(i64, i64),
+	Quality(Quality),
+	Favorite,
+	Rating(i64, i64), // stars
+	Length(i64, i64), // seconds
+	Added(i64, i64),  // seconds since the Unix epoch
+	Plays(i64, i64),
+	Skips(i64, i64),
+	Played(i64, i64), // last played, seconds since the Unix epoch
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Quality {
+	Lossless,
+	Lossy,
+	HiRes,
+}
+
+/// Filter keys understood in the find field.
+const KEYS: &[&str] = &[
+	"artist", "album", "title", "genre", "year", "type", "format", "bits", "rate", "channels", "is",
+	"rating", "length", "added", "plays", "skips", "played",
+];
+
+impl Query {
+	pub fn parse(term: &str) -> Self {
+		let mut text = Vec::new();
+		let mut filters = Vec::new();
+		for token in tokens(term) {
+			match token.split_once(':').and_then(|(key, value)| parse_filter(key, value)) {
+				Some(filter) => filters.push(filter),
+				None => text.push(token),
+			}
+		}
+		Self {
+			text: text.join(" ").to_lowercase(),
+			filters,
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.text.is_empty() && self.filters.is_empty()
+	}
+
+	pub fn matches_track(&self, track: &Track) -> bool {
+		self.matches_track_in(track, None)
+	}
+
+	/// Like `matches_track`, but `is:favorite` and `rating:` also match the
+	/// track's `album`, as they do in `matches_album`.
+	pub fn matches_track_in(&self, track: &Track, album: Option<&Album>) -> bool {
+		(self.text.is_empty()
+			|| track.track_name.to_lowercase().contains(&self.text)
+			|| track.artist.to_lowercase().contains(&self.text))
+			&& self.filters_match(track, album)
+	}
+
+	/// Text is matched against the album itself, filters against any of its
+	/// tracks; `is:favorite` and `rating:` also match the album's own.
+	pub fn matches_album(&self, album: &Album) -> bool {
+		(self.text.is_empty()
+			|| album.name.to_lowercase().contains(&self.text)
+			|| album.artist.to_lowercase().contains(&self.text))
+			&& (self.filters.is_empty() || album.tracks.iter().any(|t| self.filters_match(t, Some(album))))
+	}
+
+	fn filters_match(&self, track: &Track, album: Option<&Album>) -> bool {
+		self.filters.iter().all(|filter| match filter {
+			Filter::Artist(artist) => track.artist.to_lowercase().contains(artist),
+			Filter::Album(album) => track.album.to_lowercase().contains(album),
+			Filter::Title(title) => track.track_name.to_lowercase().contains(title),
+			Filter::Genre(genre) => track
+				.genres
+				.iter()
+				.any(|g| g.to_lowercase().contains(genre)),
+			Filter::Year(from, to) => (*from..=*to).contains(&(track.year as i64)),
+			Filter::ReleaseType(kind) => track
+				.release_type
+				.split([';', '/'])
+				.any(|t| t.trim() == kind),
+			Filter::Format(format) => track.audio.format.to_lowercase() == *format,
+			Filter::BitDepth(from, to) => (*from..=*to).contains(&(track.audio.bit_depth as i64)),
+			Filter::SampleRate(from, to) => {
+				(*from..=*to).contains(&(track.audio.sample_rate as i64))
+			}
+			Filter::Channels(from, to) => (*from..=*to).contains(&(track.audio.channels as i64)),
+			Filter::Quality(Quality::Lossless) => track.audio.is_lossless(),
+			Filter::Quality(Quality::Lossy) => !track.audio.is_lossless(),
+			Filter::Quality(Quality::HiRes) => track.audio.is_hires(),
+			Filter::Favorite => track.favorite || album.is_some_and(|a| a.favorite),
+			Filter::Rating(from, to) => {
+				(*from..=*to).contains(&(track.rating as i64))
+					|| album.is_some_and(|a| a.rating > 0 && (*from..=*to).contains(&(a.rating as i64)))
+			}
+			Filter::Length(from, to) => (*from..=*to).contains(&(track.length as i64)),
+			Filter::Added(from, to) => (*from..=*to).contains(&(track.added as i64)),
+			Filter::Plays(from, to) => (*from..=*to).contains(&(track.plays as i64)),
+			Filter::Skips(from, to) => (*from..=*to).contains(&(track.skips as i64)),
+			Filter::Played(from, to) => (*from..=*to).contains(&(track.last_played as i64)),
+		})
+	}
+}
+
+/// Add `key:value` to `term`, replacing any filter with the same key.
+pub fn with_filter(term: &str, key: &str, value: &str) -> String {
+	let value = if value.contains(' ') {
+		format!("\"{}\"", value)
+	} else {
+		value.to_string()
+	};
+	let mut kept: Vec<String> = tokens(term)
+		.into_iter()
+		.filter(|t| !t.to_lowercase().starts_with(&format!("{}:", key)))
+		.map(quote)
+		.collect();
+	kept.push(format!("{}:{}", key, value));
+	kept.join(" ")
+}
+
+/// `term` without any `key:value` token, and the value of the last one.
+pub fn take_filter(term: &str, key: &str) -> (String, Option<String>) {
+	let mut value = None;
+	let kept: Vec<String> = tokens(term)
+		.into_iter()
+		.filter(|t| match t.split_once(':') {
+			Some((k, v)) if k.eq_ignore_ascii_case(key) => {
+				value = Some(v.to_string());
+				false
+			}
+			_ => true,
+		})
+		.map(quote)
+		.collect();
+	(kept.join(" "), value)
+}
+
+/// `term` with every filter removed.
+pub fn without_filters(term: &str) -> String {
+	tokens(term)
+		.into_iter()
+		.filter(|t| {
+			!t.split_once(':')
+				.is_some_and(|(key, _)| KEYS.contains(&key.to_lowercase().as_str()))
+		})
+		.map(quote)
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+fn quote(token: String) -> String {
+	match token.split_once(':') {
+		Some((key, value)) if value.contains(' ') => format!("{}:\"{}\"", key, value),
+		_ => token,
+	}
+}
+
+/// Split on whitespace, keeping quoted parts together (without the quotes).
+fn tokens(term: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut current = String::new();
+	let mut quoted = false;
+	for c in term.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			c if c.is_whitespace() && !quoted => {
+				if !current.is_empty() {
+					tokens.push(std::mem::take(&mut current));
+				}
+			}
+			c => current.push(c),
+		}
+	}
+	if !current.is_empty() {
+		tokens.push(current);
+	}
+	tokens
+}
+
+fn parse_filter(key: &str, value: &str) -> Option<Filter> {
+	let value = value.trim().to_lowercase();
+	if value.is_empty() {
+		return None;
+	}
+	match key.to_lowercase().as_str() {
+		"artist" => Some(Filter::Artist(value)),
+		"album" => Some(Filter::Album(value)),
+		"title" => Some(Filter::Title(value)),
+		"genre" => Some(Filter::Genre(value)),
+		"type" => Some(Filter::ReleaseType(value)),
+		"year" => parse_years(&value).map(|(from, to)| Filter::Year(from, to)),
+		"format" => Some(Filter::Format(value)),
+		"bits" => parse_range(&value, parse_int).map(|(from, to)| Filter::BitDepth(from, to)),
+		"rate" => parse_range(&value, parse_khz).map(|(from, to)| Filter::SampleRate(from, to)),
+		"channels" => parse_range(&value, parse_int).map(|(from, to)| Filter::Channels(from, to)),
+		"is" => match value.as_str() {
+			"lossless" => Some(Filter::Quality(Quality::Lossless)),
+			"lossy" => Some(Filter::Quality(Quality::Lossy)),
+			"hires" | "hi-res" => Some(Filter::Quality(Quality::HiRes)),
+			"favorite" | "fav" => Some(Filter::Favorite),
+			_ => None,
+		},
+		"rating" => parse_count(&value).map(|(from, to)| Filter::Rating(from, to)),
+		"length" => parse_range(&value, parse_duration).map(|(from, to)| Filter::Length(from, to)),
+		"added" => parse_age(&value).map(|(from, to)| Filter::Added(from, to)),
+		"plays" => parse_count(&value).map(|(from, to)| Filter::Plays(from, to)),
+		"skips" => parse_count(&value).map(|(from, to)| Filter::Skips(from, to)),
+		// skips count as played here, so this is what plays:0 isn't
+		"played" if value == "never" => Some(Filter::Played(0, 0)),
+		"played" => parse_age(&value).map(|(from, to)| Filter::Played(from, to)),
+		_ => None,
+	}
+}
+
+/// Like `parse_range`, but for counts where zero is a real value, so "<3"
+/// includes it.
+fn parse_count(value: &str) -> Option<(i64, i64)> {
+	let (from, to) = parse_range(value, parse_int)?;
+	Some(if value.starts_with('<') { (0, to) } else { (from, to) })
+}
+
+/// Days since a file was added or a track last played ("<30d", ">365d",
+/// "0d" for today) as a range of timestamps.
+fn parse_age(value: &str) -> Option<(i64, i64)> {
+	let (from, to) = parse_count(value.trim_end_matches('d'))?;
+	let now = history::now() as i64;
+	let newest = now.checked_sub(from.checked_mul(86400)?)?;
+	let oldest = match to {
+		i64::MAX => 1,
+		to => now.checked_sub(to.checked_add(1)?.checked_mul(86400)?)? + 1,
+	};
+	Some((oldest, newest))
+}
+
+/// "1994", "1990-1999", "1990s", "90s", ">2000", ">=2000", "<1980", "<=1980".
+fn parse_years(value: &str) -> Option<(i64, i64)> {
+	if let Some(decade) = value.strip_suffix('s') {
+		let start: i64 = decade.parse().ok()?;
+		// two digits are the latest such decade that has begun: "90s" means
+		// the 1990s, "20s" the 2020s
+		let start = if (0..100).contains(&start) {
+			let year = history::current_year();
+			let in_this_century = year / 100 * 100 + start;
+			if in_this_century <= year {
+				in_this_century
+			} else {
+				in_this_century - 100
+			}
+		} else {
+			start
+		};
+		return Some((start, start.checked_add(9)?));
+	}
+	parse_range(value, parse_int)
+}
+
+/// "n", "a-b", ">n", ">=n", "<n" or "<=n" as an inclusive range. Zero
+/// stands for unknown, so open ranges start at 1.
+/// Values that don't fit give `None`, like anything else that doesn't parse.
+fn parse_range(value: &str, parse: fn(&str) -> Option<i64>) -> Option<(i64, i64)> {
+	if let Some((from, to)) = value.split_once('-') {
+		return Some((parse(from)?, parse(to)?));
+	}
+	if let Some(v) = value.strip_prefix(">=") {
+		return Some((parse(v)?, i64::MAX));
+	}
+	if let Some(v) = value.strip_prefix("<=") {
+		return Some((1, parse(v)?));
+	}
+	if let Some(v) = value.strip_prefix('>') {
+		return Some((parse(v)?.checked_add(1)?, i64::MAX));
+	}
+	if let Some(v) = value.strip_prefix('<') {
+		return Some((1, parse(v)?.checked_sub(1)?));
+	}
+	let n = parse(value)?;
+	Some((n, n))
+}
+
+fn parse_int(value: &str) -> Option<i64> {
+	value.trim().parse().ok()
+}
+
+/// Seconds from "240", "240s", "4m", "1h" or "3:30".
+fn parse_duration(value: &str) -> Option<i64> {
+	let value = value.trim();
+	if let Some((minutes, seconds)) = value.split_once(':') {
+		return parse_int(minutes)?.checked_mul(60)?.checked_add(parse_int(seconds)?);
+	}
+	if let Some(minutes) = value.strip_suffix('m') {
+		return parse_int(minutes)?.checked_mul(60);
+	}
+	if let Some(hours) = value.strip_suffix('h') {
+		return parse_int(hours)?.checked_mul(3600);
+	}
+	parse_int(value.trim_end_matches('s'))
+}
+
+/// Sample rates in Hz, or in kHz when small: "44.1", "96", "96khz" and "96000" all work.
+fn parse_khz(value: &str) -> Option<i64> {
+	let value = value.trim().trim_end_matches("khz").trim_end_matches("hz");
+	let rate: f64 = value.parse().ok()?;
+	Some(if rate < 1000.0 { (rate * 1000.0).round() as i64 } else { rate as i64 })
+}
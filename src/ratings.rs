@@ -0,0 +1,190 @@
+use crate::tag_editor;
+use lofty::aac::AacFile;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::id3::v2::{Frame, Id3v2Tag, PopularimeterFrame};
+use lofty::iff::aiff::AiffFile;
+use lofty::iff::wav::WavFile;
+use lofty::mpeg::MpegFile;
+use lofty::prelude::ItemKey;
+use lofty::probe::Probe;
+use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+use std::fs::File;
+use std::io::BufReader;
+
+pub const MAX_STARS: u8 = 5;
+
+// the POPM email Windows Media Player uses, which most other players read too
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+const FMPS_RATING: &str = "FMPS_RATING";
+
+/// "★★★☆☆", or nothing if unrated.
+pub fn stars(rating: u8) -> String {
+	if rating == 0 {
+		return String::new();
+	}
+	let rating = rating.min(MAX_STARS) as usize;
+	format!("{}{}", "★".repeat(rating), "☆".repeat(MAX_STARS as usize - rating))
+}
+
+/// The star rating in a tag: FMPS_RATING (0.0 to 1.0), then POPM in ID3v2,
+/// RATING in Vorbis comments or "rate" in MP4. 0 if there is none.
+pub fn read(tag: &Tag) -> u8 {
+	let fmps = tag
+		.items()
+		.find(|item| matches!(item.key(), ItemKey::Unknown(key) if key.eq_ignore_ascii_case(FMPS_RATING)))
+		.and_then(|item| item.value().text())
+		.and_then(|text| text.trim().parse::<f64>().ok());
+	if let Some(fraction) = fmps {
+		return (fraction.clamp(0.0, 1.0) * MAX_STARS as f64).round() as u8;
+	}
+	match tag.get(&ItemKey::Popularimeter).map(|item| item.value()) {
+		Some(ItemValue::Binary(popm)) => {
+			// email, a zero byte, the rating and a play counter
+			let rating = popm
+				.iter()
+				.position(|b| *b == 0)
+				.and_then(|end| popm.get(end + 1))
+				.copied()
+				.unwrap_or_default();
+			from_popm(rating)
+		}
+		Some(ItemValue::Text(text)) => from_text(text),
+		_ => 0,
+	}
+}
+
+/// Read a probed file like `Probe::read` does, along with the star rating in
+/// its ID3v2 POPM frame. lofty leaves POPM out of the generic tag, so it's
+/// taken from the file's own tag before that is converted; this way the
+/// library scan still reads every file only once. The rating is 0 if there is
+/// no POPM frame.
+pub fn read_probed(probe: Probe<BufReader<File>>) -> lofty::error::Result<(TaggedFile, u8)> {
+	match probe.file_type() {
+		Some(FileType::Mpeg) => read_with_popm(probe, MpegFile::id3v2),
+		Some(FileType::Wav) => read_with_popm(probe, WavFile::id3v2),
+		Some(FileType::Aiff) => read_with_popm(probe, AiffFile::id3v2),
+		Some(FileType::Aac) => read_with_popm(probe, AacFile::id3v2),
+		_ => Ok((probe.read()?, 0)),
+	}
+}
+
+fn read_with_popm<F: AudioFile + Into<TaggedFile>>(
+	probe: Probe<BufReader<File>>,
+	id3v2: fn(&F) -> Option<&Id3v2Tag>,
+) -> lofty::error::Result<(TaggedFile, u8)> {
+	let file = F::read_from(&mut probe.into_inner(), ParseOptions::new())?;
+	let rating = id3v2(&file).map(popm_rating).unwrap_or_default();
+	Ok((file.into(), rating))
+}
+
+/// The rating in our own POPM frame, or else in the first one another player
+/// left with a rating.
+fn popm_rating(tag: &Id3v2Tag) -> u8 {
+	let ratings: Vec<(&str, u8)> = tag
+		.into_iter()
+		.filter_map(|frame| match frame {
+			Frame::Popularimeter(popm) => Some((popm.email.as_str(), popm.rating)),
+			_ => None,
+		})
+		.collect();
+	ratings
+		.iter()
+		.find(|(email, _)| *email == POPM_EMAIL)
+		.or_else(|| ratings.iter().find(|(_, rating)| *rating > 0))
+		.map(|(_, rating)| from_popm(*rating))
+		.unwrap_or_default()
+}
+
+/// POPM ratings go from 1 to 255; these are the ranges Windows Media Player uses.
+fn from_popm(rating: u8) -> u8 {
+	match rating {
+		0 => 0,
+		1..=31 => 1,
+		32..=95 => 2,
+		96..=159 => 3,
+		160..=223 => 4,
+		_ => 5,
+	}
+}
+
+/// Star ratings are written as 1 to 5 by some players and 0 to 100 by others.
+fn from_text(text: &str) -> u8 {
+	match text.trim().parse::<u32>() {
+		Ok(n) if n <= MAX_STARS as u32 => n as u8,
+		Ok(n) => (n.min(100) as f64 / 20.0).round() as u8,
+		Err(_) => 0,
+	}
+}
+
+/// Write `rating` to the tags of the file at `path`, or remove it if 0.
+pub fn write(path: &str, rating: u8) -> Result<(), String> {
+	let rating = rating.min(MAX_STARS);
+	// ID3v2 goes through the file's own tag, as saving the generic one
+	// would drop POPM along with every other frame lofty doesn't map
+	if let Some(result) = tag_editor::edit_id3v2(path, |tag| {
+		write_popm(tag, rating);
+		Ok(())
+	}) {
+		return result;
+	}
+	let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+	if tagged_file.primary_tag().is_none() {
+		let tag_type = tagged_file.primary_tag_type();
+		tagged_file.insert_tag(Tag::new(tag_type));
+	}
+	let Some(tag) = tagged_file.primary_tag_mut() else {
+		return Err(String::from("file has no writable tag"));
+	};
+	match tag.tag_type() {
+		TagType::VorbisComments => {
+			// keep RATING on the scale it was written in
+			let scale_100 = tag
+				.get_string(&ItemKey::Popularimeter)
+				.and_then(|text| text.trim().parse::<u32>().ok())
+				.map(|n| n > MAX_STARS as u32);
+			let fmps = ItemKey::Unknown(String::from(FMPS_RATING));
+			tag.remove_key(&fmps);
+			if rating > 0 {
+				let fraction = rating as f64 / MAX_STARS as f64;
+				tag.insert_unchecked(TagItem::new(fmps, ItemValue::Text(fraction.to_string())));
+			}
+			if let Some(scale_100) = scale_100 {
+				tag.remove_key(&ItemKey::Popularimeter);
+				if rating > 0 {
+					let value = if scale_100 { rating as u32 * 20 } else { rating as u32 };
+					tag.insert_text(ItemKey::Popularimeter, value.to_string());
+				}
+			}
+		}
+		TagType::Mp4Ilst => {
+			tag.remove_key(&ItemKey::Popularimeter);
+			if rating > 0 {
+				tag.insert_text(ItemKey::Popularimeter, (rating as u32 * 20).to_string());
+			}
+		}
+		other => return Err(format!("{:?} tags have no rating", other)),
+	}
+	tagged_file
+		.save_to_path(path, WriteOptions::default())
+		.map_err(|e| e.to_string())
+}
+
+/// Replace our POPM frame in `tag`. Frames other players wrote, told
+/// apart by their email, are kept.
+fn write_popm(tag: &mut Id3v2Tag, rating: u8) {
+	let counter = (&*tag)
+		.into_iter()
+		.find_map(|frame| match frame {
+			Frame::Popularimeter(popm) if popm.email == POPM_EMAIL => Some(popm.counter),
+			_ => None,
+		})
+		.unwrap_or_default();
+	tag.retain(|frame| !matches!(frame, Frame::Popularimeter(popm) if popm.email == POPM_EMAIL));
+	// a play counter outlives the rating, as 0 stands for "not rated"
+	if rating > 0 || counter > 0 {
+		let byte = [0, 1, 64, 128, 196, 255][rating as usize];
+		let popm = PopularimeterFrame::new(POPM_EMAIL.to_string(), byte, counter);
+		tag.insert(Frame::Popularimeter(popm));
+	}
+}
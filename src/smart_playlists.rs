@@ -0,0 +1,143 @@
+use crate::load_album_and_track_lists::{Album, Track};
+use crate::query::{self, Query};
+use rand::rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// A playlist made from find rules such as `is:favorite genre:jazz`, filled
+/// from the library every time it is opened. Kept in the config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+	pub name: String,
+	pub rules: String, // a find term
+	#[serde(default)]
+	pub sort: Sort,
+	#[serde(default)]
+	pub limit: usize, // tracks, 0 for all of them
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+	#[default]
+	Artist, // then album, disc and track number
+	Title,
+	Year,   // newest first
+	Added,  // most recently added first
+	Length, // shortest first
+	Plays,  // most played first
+	Rating, // most stars first
+	Random,
+}
+
+impl Sort {
+	const ALL: [Sort; 8] = [
+		Sort::Artist,
+		Sort::Title,
+		Sort::Year,
+		Sort::Added,
+		Sort::Length,
+		Sort::Plays,
+		Sort::Rating,
+		Sort::Random,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Sort::Artist => "artist",
+			Sort::Title => "title",
+			Sort::Year => "year",
+			Sort::Added => "added",
+			Sort::Length => "length",
+			Sort::Plays => "plays",
+			Sort::Rating => "rating",
+			Sort::Random => "random",
+		}
+	}
+}
+
+impl SmartPlaylist {
+	/// Read a definition like "Recent jazz = genre:jazz added:<30d limit:50 sort:added".
+	pub fn parse(definition: &str) -> Result<Self, String> {
+		let Some((name, rules)) = definition.split_once('=') else {
+			return Err(String::from("Write it as \"name = rules\", e.g. \"Jazz = genre:jazz is:favorite\""));
+		};
+		let name = name.trim();
+		if name.is_empty() {
+			return Err(String::from("The smart playlist needs a name"));
+		}
+		let (rules, limit) = query::take_filter(rules, "limit");
+		let (rules, sort) = query::take_filter(&rules, "sort");
+		let limit = match limit {
+			Some(n) => n
+				.parse()
+				.map_err(|_| format!("\"limit:{}\" needs a number of tracks", n))?,
+			None => 0,
+		};
+		let sort = match sort {
+			Some(key) => Sort::ALL
+				.into_iter()
+				.find(|s| s.name() == key.to_lowercase())
+				.ok_or_else(|| {
+					let names: Vec<&str> = Sort::ALL.iter().map(|s| s.name()).collect();
+					format!("\"sort:{}\" should be one of {}", key, names.join(", "))
+				})?,
+			None => Sort::default(),
+		};
+		Ok(Self {
+			name: name.to_string(),
+			rules,
+			sort,
+			limit,
+		})
+	}
+
+	/// The definition `parse` reads, for editing.
+	pub fn definition(&self) -> String {
+		let mut definition = format!("{} = {}", self.name, self.rules);
+		if self.sort != Sort::default() {
+			definition.push_str(&format!(" sort:{}", self.sort.name()));
+		}
+		if self.limit > 0 {
+			definition.push_str(&format!(" limit:{}", self.limit));
+		}
+		definition
+	}
+
+	/// The tracks matching the rules, sorted and cut to the limit. Tracks
+	/// match the favorite flag and rating of their album in `albums` too.
+	pub fn evaluate(&self, tracks: &[Track], albums: &[Album]) -> Vec<Track> {
+		let query = Query::parse(&self.rules);
+		let albums: HashMap<(&str, &str), &Album> = albums
+			.iter()
+			.map(|a| ((a.artist.as_str(), a.name.as_str()), a))
+			.collect();
+		let mut found: Vec<Track> = tracks
+			.iter()
+			.filter(|t| {
+				let album = albums.get(&(t.album_artist.as_str(), t.album.as_str()));
+				query.matches_track_in(t, album.copied())
+			})
+			.cloned()
+			.collect();
+		match self.sort {
+			Sort::Artist => found.sort_by(|a, b| {
+				(a.artist.to_lowercase(), a.album.to_lowercase(), a.disc_num, a.track_num)
+					.cmp(&(b.artist.to_lowercase(), b.album.to_lowercase(), b.disc_num, b.track_num))
+			}),
+			Sort::Title => found.sort_by_key(|t| t.track_name.to_lowercase()),
+			Sort::Year => found.sort_by_key(|t| Reverse(t.year)),
+			Sort::Added => found.sort_by_key(|t| Reverse(t.added)),
+			Sort::Length => found.sort_by_key(|t| t.length),
+			Sort::Plays => found.sort_by_key(|t| Reverse(t.plays)),
+			Sort::Rating => found.sort_by_key(|t| Reverse(t.rating)),
+			Sort::Random => found.shuffle(&mut rng()),
+		}
+		if self.limit > 0 {
+			found.truncate(self.limit);
+		}
+		found
+	}
+}
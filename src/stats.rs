@@ -0,0 +1,93 @@
+use crate::load_album_and_track_lists::{Album, COMMON_TAGS, Track};
+use std::collections::{HashMap, HashSet};
+
+const TOP_ROWS: usize = 10; // rows in the per-artist and largest album charts
+
+/// Numbers about the whole library, shown in the statistics popup.
+#[derive(Debug, Clone, Default)]
+pub struct LibraryStats {
+	pub tracks: usize,
+	pub albums: usize,
+	pub artists: usize,
+	pub playtime: u64,                          // seconds
+	pub artist_playtime: Vec<(String, u64)>,    // longest first
+	pub formats: Vec<(String, u64)>,            // tracks per format, most first
+	pub sample_rates: Vec<(u32, u64)>,          // tracks per sample rate in Hz, lowest first
+	pub largest_albums: Vec<(String, u64)>,     // "artist - album" by number of tracks
+	pub missing_tags: Vec<(&'static str, u64)>, // tracks without each tag
+}
+
+impl LibraryStats {
+	pub fn new(tracks: &[Track], albums: &[Album]) -> Self {
+		let artists: HashSet<&str> = tracks.iter().map(|t| t.artist.as_str()).collect();
+
+		let mut artist_playtime: HashMap<&str, u64> = HashMap::new();
+		let mut formats: HashMap<&str, u64> = HashMap::new();
+		let mut sample_rates: HashMap<u32, u64> = HashMap::new();
+		let mut missing_tags: Vec<(&'static str, u64)> =
+			COMMON_TAGS.into_iter().map(|name| (name, 0)).collect();
+		for track in tracks {
+			*artist_playtime.entry(&track.artist).or_default() += track.length;
+			*formats.entry(&track.audio.format).or_default() += 1;
+			*sample_rates.entry(track.audio.sample_rate).or_default() += 1;
+			for name in track.missing_tags() {
+				if let Some((_, n)) = missing_tags.iter_mut().find(|(m, _)| *m == name) {
+					*n += 1;
+				}
+			}
+		}
+
+		let mut artist_playtime: Vec<(String, u64)> = artist_playtime
+			.into_iter()
+			.map(|(artist, secs)| (artist.to_string(), secs))
+			.collect();
+		artist_playtime.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+		artist_playtime.truncate(TOP_ROWS);
+
+		let mut formats: Vec<(String, u64)> = formats
+			.into_iter()
+			.map(|(format, n)| (format.to_string(), n))
+			.collect();
+		formats.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+		let mut sample_rates: Vec<(u32, u64)> = sample_rates.into_iter().collect();
+		sample_rates.sort();
+
+		let mut largest: Vec<&Album> = albums.iter().collect();
+		largest.sort_by(|a, b| {
+			b.tracks
+				.len()
+				.cmp(&a.tracks.len())
+				.then_with(|| (&a.artist, &a.name).cmp(&(&b.artist, &b.name)))
+		});
+		let largest_albums = largest
+			.into_iter()
+			.take(TOP_ROWS)
+			.map(|a| (format!("{} - {}", a.artist, a.name), a.tracks.len() as u64))
+			.collect();
+
+		Self {
+			tracks: tracks.len(),
+			albums: albums.len(),
+			artists: artists.len(),
+			playtime: tracks.iter().map(|t| t.length).sum(),
+			artist_playtime,
+			formats,
+			sample_rates,
+			largest_albums,
+			missing_tags,
+		}
+	}
+}
+
+/// "2d 4h 07m", "4h 07m" or "7m 12s".
+pub fn format_playtime(secs: u64) -> String {
+	let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+	if days > 0 {
+		format!("{}d {}h {:02}m", days, hours, minutes)
+	} else if hours > 0 {
+		format!("{}h {:02}m", hours, minutes)
+	} else {
+		format!("{}m {:02}s", minutes, secs % 60)
+	}
+}
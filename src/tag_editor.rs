@@ -0,0 +1,383 @@
+use crate::load_album_and_track_lists::{Track, UNKNOWN_ALBUM, UNKNOWN_ARTIST, UNKNOWN_TITLE};
+use lofty::aac::AacFile;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::id3::v2::Id3v2Tag;
+use lofty::iff::aiff::AiffFile;
+use lofty::iff::wav::WavFile;
+use lofty::mpeg::MpegFile;
+use lofty::prelude::{Accessor, ItemKey, MergeTag, SplitTag};
+use lofty::probe::Probe;
+use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TagField {
+	Title,
+	Artist,
+	AlbumArtist,
+	Album,
+	TrackNum,
+	DiscNum,
+	Year,
+	Genre,
+}
+
+impl TagField {
+	pub const ALL: [TagField; 8] = [
+		TagField::Title,
+		TagField::Artist,
+		TagField::AlbumArtist,
+		TagField::Album,
+		TagField::TrackNum,
+		TagField::DiscNum,
+		TagField::Year,
+		TagField::Genre,
+	];
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			TagField::Title => "Title",
+			TagField::Artist => "Artist",
+			TagField::AlbumArtist => "Album artist",
+			TagField::Album => "Album",
+			TagField::TrackNum => "Track",
+			TagField::DiscNum => "Disc",
+			TagField::Year => "Year",
+			TagField::Genre => "Genre",
+		}
+	}
+
+	/// Fields that differ per track and can't be set for a whole album at once.
+	pub fn is_per_track(&self) -> bool {
+		matches!(self, TagField::Title | TagField::TrackNum)
+	}
+
+	fn is_numeric(&self) -> bool {
+		matches!(self, TagField::TrackNum | TagField::DiscNum | TagField::Year)
+	}
+
+	pub fn get(&self, track: &Track) -> String {
+		let num = |n: i32| if n > 0 { n.to_string() } else { String::new() };
+		match self {
+			TagField::Title => track.track_name.clone(),
+			TagField::Artist => track.artist.clone(),
+			TagField::AlbumArtist => track.album_artist.clone(),
+			TagField::Album => track.album.clone(),
+			TagField::TrackNum => num(track.track_num),
+			TagField::DiscNum => num(track.disc_num),
+			TagField::Year => num(track.year),
+			TagField::Genre => track.genre_label(),
+		}
+	}
+
+	/// Update the in-memory track to match what was written to the file.
+	/// Cleared names fall back the way the library scan does it.
+	pub fn set(&self, track: &mut Track, value: &str) {
+		let num = value.trim().parse::<i32>().unwrap_or_default();
+		let cleared = value.trim().is_empty();
+		match self {
+			TagField::Title if cleared => {
+				let stem = Path::new(&track.path).file_stem().and_then(|s| s.to_str());
+				track.track_name = stem.unwrap_or(UNKNOWN_TITLE).to_string();
+				track.inferred.title = stem.is_some();
+			}
+			TagField::Title => {
+				track.track_name = value.to_string();
+				track.inferred.title = false;
+			}
+			TagField::Artist => {
+				track.artist = if cleared { UNKNOWN_ARTIST } else { value }.to_string();
+				track.inferred.artist = false;
+			}
+			TagField::AlbumArtist if cleared => {
+				track.album_artist = track.default_album_artist();
+				track.inferred.album_artist = !track.compilation && track.inferred.artist;
+			}
+			TagField::AlbumArtist => {
+				track.album_artist = value.to_string();
+				track.inferred.album_artist = false;
+			}
+			TagField::Album => {
+				track.album = if cleared { UNKNOWN_ALBUM } else { value }.to_string();
+				track.inferred.album = false;
+			}
+			TagField::TrackNum => {
+				track.track_num = num;
+				track.inferred.track_num = false;
+			}
+			TagField::DiscNum => {
+				track.disc_num = num;
+				track.inferred.disc_num = false;
+			}
+			TagField::Year => track.year = num,
+			TagField::Genre => {
+				track.genres = value
+					.split(';')
+					.map(|g| g.trim().to_string())
+					.filter(|g| !g.is_empty())
+					.collect()
+			}
+		}
+	}
+
+	fn write(&self, tag: &mut Tag, value: &str) -> Result<(), String> {
+		let value = value.trim();
+		if value.is_empty() {
+			match self {
+				TagField::Title => tag.remove_title(),
+				TagField::Artist => tag.remove_artist(),
+				TagField::AlbumArtist => tag.remove_key(&ItemKey::AlbumArtist),
+				TagField::Album => tag.remove_album(),
+				TagField::TrackNum => tag.remove_track(),
+				TagField::DiscNum => tag.remove_disk(),
+				TagField::Year => tag.remove_year(),
+				TagField::Genre => tag.remove_genre(),
+			}
+			return Ok(());
+		}
+		let num = || {
+			value
+				.parse::<u32>()
+				.map_err(|_| format!("{} must be a number", self.label()))
+		};
+		match self {
+			TagField::Title => tag.set_title(value.to_string()),
+			TagField::Artist => tag.set_artist(value.to_string()),
+			TagField::AlbumArtist => {
+				tag.insert_text(ItemKey::AlbumArtist, value.to_string());
+			}
+			TagField::Album => tag.set_album(value.to_string()),
+			TagField::TrackNum => tag.set_track(num()?),
+			TagField::DiscNum => tag.set_disk(num()?),
+			TagField::Year => tag.set_year(num()?),
+			TagField::Genre => {
+				// one GENRE comment per genre in Vorbis comments, "a; b" elsewhere
+				let genres: Vec<&str> = value.split(';').map(str::trim).filter(|g| !g.is_empty()).collect();
+				if tag.tag_type() == TagType::VorbisComments {
+					tag.remove_key(&ItemKey::Genre);
+					for genre in &genres {
+						tag.push(TagItem::new(ItemKey::Genre, ItemValue::Text(genre.to_string())));
+					}
+				} else {
+					tag.insert_text(ItemKey::Genre, genres.join("; "));
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Check a value before anything is written, so a typo in a number field
+/// doesn't leave an album half edited.
+pub fn validate(field: TagField, value: &str) -> Result<(), String> {
+	let value = value.trim();
+	if field.is_numeric() && !value.is_empty() && value.parse::<u32>().is_err() {
+		return Err(format!("{} must be a number", field.label()));
+	}
+	Ok(())
+}
+
+/// Write `changes` to the primary tag of the file at `path`, creating the
+/// tag if the file has none.
+pub fn write_tags(path: &str, changes: &[(TagField, String)]) -> Result<(), String> {
+	let write = |tag: &mut Tag| {
+		changes
+			.iter()
+			.try_for_each(|(field, value)| field.write(tag, value))
+	};
+	// only the frames lofty maps are split off into the generic tag, so
+	// ratings, synced lyrics and the like stay in the remainder
+	if let Some(result) = edit_id3v2(path, |id3v2| {
+		let (remainder, mut tag) = std::mem::take(id3v2).split_tag();
+		write(&mut tag)?;
+		*id3v2 = remainder.merge_tag(tag);
+		Ok(())
+	}) {
+		return result;
+	}
+	let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+	if tagged_file.primary_tag().is_none() {
+		let tag_type = tagged_file.primary_tag_type();
+		tagged_file.insert_tag(Tag::new(tag_type));
+	}
+	let Some(tag) = tagged_file.primary_tag_mut() else {
+		return Err(String::from("file has no writable tag"));
+	};
+	write(tag)?;
+	tagged_file
+		.save_to_path(path, WriteOptions::default())
+		.map_err(|e| e.to_string())
+}
+
+/// Apply `edit` to the ID3v2 tag of the file at `path` and save it, creating
+/// the tag if there is none. Saving the generic tag instead would drop every
+/// frame lofty doesn't map, like POPM and SYLT. `None` if the file's format
+/// doesn't use ID3v2.
+pub fn edit_id3v2(
+	path: &str,
+	edit: impl FnOnce(&mut Id3v2Tag) -> Result<(), String>,
+) -> Option<Result<(), String>> {
+	let probe = Probe::open(path)
+		.map_err(|e| e.to_string())
+		.and_then(|probe| probe.guess_file_type().map_err(|e| e.to_string()));
+	let file_type = match probe {
+		Ok(probe) => probe.file_type(),
+		Err(e) => return Some(Err(e)),
+	};
+	let result = match file_type {
+		Some(FileType::Mpeg) => edit_file(path, edit, MpegFile::remove_id3v2, MpegFile::set_id3v2),
+		Some(FileType::Wav) => edit_file(path, edit, WavFile::remove_id3v2, WavFile::set_id3v2),
+		Some(FileType::Aiff) => edit_file(path, edit, AiffFile::remove_id3v2, AiffFile::set_id3v2),
+		Some(FileType::Aac) => edit_file(path, edit, AacFile::remove_id3v2, AacFile::set_id3v2),
+		_ => return None,
+	};
+	Some(result)
+}
+
+fn edit_file<F: AudioFile>(
+	path: &str,
+	edit: impl FnOnce(&mut Id3v2Tag) -> Result<(), String>,
+	remove_id3v2: fn(&mut F) -> Option<Id3v2Tag>,
+	set_id3v2: fn(&mut F, Id3v2Tag) -> Option<Id3v2Tag>,
+) -> Result<(), String> {
+	let mut file = File::open(path).map_err(|e| e.to_string())?;
+	let mut audio_file = F::read_from(&mut file, ParseOptions::new().read_properties(false))
+		.map_err(|e| e.to_string())?;
+	let mut tag = remove_id3v2(&mut audio_file).unwrap_or_default();
+	edit(&mut tag)?;
+	set_id3v2(&mut audio_file, tag);
+	audio_file
+		.save_to_path(path, WriteOptions::default())
+		.map_err(|e| e.to_string())
+}
+
+pub struct FieldInput {
+	pub field: TagField,
+	pub value: String,
+	pub original: Option<String>, // None if the edited tracks disagree
+	pub editable: bool,
+}
+
+impl FieldInput {
+	pub fn is_changed(&self) -> bool {
+		match &self.original {
+			Some(original) => &self.value != original,
+			None => !self.value.is_empty(),
+		}
+	}
+}
+
+/// Popup form for editing the tags of one track or a whole album.
+pub struct TagEditor {
+	pub paths: Vec<String>,
+	pub fields: Vec<FieldInput>,
+	pub selected: usize,
+	pub cursor: usize, // char index in the selected field
+	pub error: Option<String>,
+}
+
+impl TagEditor {
+	pub fn new(tracks: &[Track]) -> Self {
+		let single = tracks.len() == 1;
+		let fields = TagField::ALL
+			.iter()
+			.map(|&field| {
+				let first = tracks.first().map(|t| field.get(t)).unwrap_or_default();
+				let same = tracks.iter().all(|t| field.get(t) == first);
+				let original = if same { Some(first.clone()) } else { None };
+				FieldInput {
+					field,
+					value: original.clone().unwrap_or_default(),
+					original,
+					editable: single || !field.is_per_track(),
+				}
+			})
+			.collect();
+		let mut editor = Self {
+			paths: tracks.iter().map(|t| t.path.clone()).collect(),
+			fields,
+			selected: 0,
+			cursor: 0,
+			error: None,
+		};
+		if !single {
+			editor.selected = editor.fields.iter().position(|f| f.editable).unwrap_or(0);
+		}
+		editor.cursor_to_end();
+		editor
+	}
+
+	/// The fields the user changed, with their new values.
+	pub fn changes(&self) -> Vec<(TagField, String)> {
+		self.fields
+			.iter()
+			.filter(|f| f.editable && f.is_changed())
+			.map(|f| (f.field, f.value.trim().to_string()))
+			.collect()
+	}
+
+	fn cursor_to_end(&mut self) {
+		self.cursor = self.fields[self.selected].value.chars().count();
+	}
+
+	pub fn next_field(&mut self) {
+		let len = self.fields.len();
+		for step in 1..=len {
+			let i = (self.selected + step) % len;
+			if self.fields[i].editable {
+				self.selected = i;
+				break;
+			}
+		}
+		self.cursor_to_end();
+	}
+
+	pub fn prev_field(&mut self) {
+		let len = self.fields.len();
+		for step in 1..=len {
+			let i = (self.selected + len - step) % len;
+			if self.fields[i].editable {
+				self.selected = i;
+				break;
+			}
+		}
+		self.cursor_to_end();
+	}
+
+	pub fn move_cursor_left(&mut self) {
+		self.cursor = self.cursor.saturating_sub(1);
+	}
+
+	pub fn move_cursor_right(&mut self) {
+		let len = self.fields[self.selected].value.chars().count();
+		self.cursor = (self.cursor + 1).min(len);
+	}
+
+	pub fn enter_char(&mut self, c: char) {
+		let field = &mut self.fields[self.selected];
+		let index = field
+			.value
+			.char_indices()
+			.map(|(i, _)| i)
+			.nth(self.cursor)
+			.unwrap_or(field.value.len());
+		field.value.insert(index, c);
+		self.cursor += 1;
+	}
+
+	pub fn delete_char(&mut self) {
+		if self.cursor == 0 {
+			return;
+		}
+		let field = &mut self.fields[self.selected];
+		field.value = field
+			.value
+			.chars()
+			.take(self.cursor - 1)
+			.chain(field.value.chars().skip(self.cursor))
+			.collect();
+		self.cursor -= 1;
+	}
+}